@@ -0,0 +1,203 @@
+//! The typed form of an MDL script. Scripts are parsed and checked into this once, and the
+//! interpreter then runs the statements for every frame without touching the source again.
+
+use crate::color::Color;
+use crate::error::Span;
+use crate::image::{Filter, LineStyle, Shading, ToneMap};
+use crate::matrix::CurveType;
+use shunting::RPNExpr;
+
+/// a piece of a script along with where it was written
+#[derive(Clone, Debug)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Spanned<T> {
+        Spanned { node, span }
+    }
+}
+
+/// a name refering to constants, a coordinate system or a knob
+pub type Name = Spanned<String>;
+pub type Statement = Spanned<Command>;
+
+#[derive(Clone, Debug, Default)]
+pub struct Script {
+    pub settings: Settings,
+    /// knobs given one value for the whole animation with set
+    pub variables: Vec<Spanned<Variable>>,
+    pub varies: Vec<Spanned<Vary>>,
+    /// the commands run for every frame, in order
+    pub statements: Vec<Statement>,
+    /// the script and every file it includes, as named when they were read
+    pub sources: Vec<String>,
+}
+
+/// commands that configure the whole render no matter where they appear in the script
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    pub frames: Option<Spanned<usize>>,
+    pub basename: Option<String>,
+    pub background: Option<Spanned<Background>>,
+    /// supersampling factor and the filter used to bring it back down
+    pub antialias: Option<(usize, Filter)>,
+    pub tone_map: Option<ToneMap>,
+    /// in stops, only used with hdr on
+    pub exposure: f32,
+    /// also write an html page to flip through the rendered frames
+    pub web: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum Background {
+    Solid(Color),
+    /// top color, then bottom color
+    Gradient(Color, Color),
+    /// path of a ppm picture
+    Picture(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Variable {
+    pub name: String,
+    pub value: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Vary {
+    pub knob: String,
+    pub start_frame: usize,
+    /// inclusive
+    pub end_frame: usize,
+    pub curve: VaryCurve,
+}
+
+#[derive(Clone, Debug)]
+pub enum VaryCurve {
+    /// eased by raising the progress through the frames to a power, 1 for a straight line
+    Linear { start_value: f32, end_value: f32, power: f32 },
+    /// an equation of {frame_num}, clamped to the given range
+    Equation { equation: Spanned<String>, min_value: f32, max_value: f32 },
+}
+
+/// a numeric argument of a command
+#[derive(Clone, Debug)]
+pub enum Value {
+    Number(f32),
+    /// worked out every frame from the knobs and frame_num
+    Expression(Box<Expression>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Expression {
+    pub text: String,
+    pub rpn: RPNExpr,
+    pub span: Span,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    pub fn holds(&self, left: f32, right: f32) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Greater => left > right,
+        }
+    }
+}
+
+/// holds when the comparison does, or without a comparison when the value is not 0
+#[derive(Clone, Debug)]
+pub struct Condition {
+    pub left: Value,
+    pub comparison: Option<(Comparison, Value)>,
+}
+
+/// how many points go around curved shapes
+#[derive(Clone, Debug)]
+pub enum Detail {
+    Steps(Value),
+    /// picked for each shape from how big it will be on the screen
+    Auto,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Constants {
+        name: String,
+        /// scales every reflection value when given
+        knob: Option<Name>,
+        /// ambient, diffuse and specular for red, then green, then blue
+        reflect: [Value; 9],
+    },
+    /// copies the top of the stack, or the named coordinate system
+    Push(Option<Name>),
+    Pop,
+    Move { x: Value, y: Value, z: Value, knob: Option<Name> },
+    Rotate { axis: Axis, degrees: Value, knob: Option<Name> },
+    Scale { x: Value, y: Value, z: Value, knob: Option<Name> },
+    Sphere { constants: Option<Name>, x: Value, y: Value, z: Value, radius: Value, coord_system: Option<Name> },
+    Box { constants: Option<Name>, x: Value, y: Value, z: Value, width: Value, height: Value, depth: Value, coord_system: Option<Name> },
+    Torus { constants: Option<Name>, x: Value, y: Value, z: Value, inner_radius: Value, outer_radius: Value, coord_system: Option<Name> },
+    Line { x0: Value, y0: Value, z0: Value, x1: Value, y1: Value, z1: Value },
+    Circle { x: Value, y: Value, z: Value, radius: Value },
+    /// x0 y0 x1 y1 then the other two control points of a bezier, or the two rates of
+    /// change of a hermite
+    Curve { curve_type: CurveType, points: [Value; 8] },
+    Detail(Detail),
+    LineStyle(LineStyle),
+    LineWidth(Value),
+    Shading(Shading),
+    SaveCoordSystem(String),
+    Display,
+    Save(String),
+    SaveDepth(String),
+    If { condition: Condition, then_statements: Vec<Statement>, else_statements: Vec<Statement> },
+}
+
+impl Command {
+    /// every numeric argument, in the order they are written
+    pub fn values(&self) -> Vec<&Value> {
+        match self {
+            Command::Constants { reflect, .. } => reflect.iter().collect(),
+            Command::Move { x, y, z, .. } | Command::Scale { x, y, z, .. } => vec![x, y, z],
+            Command::Rotate { degrees, .. } => vec![degrees],
+            Command::Sphere { x, y, z, radius, .. } => vec![x, y, z, radius],
+            Command::Box { x, y, z, width, height, depth, .. } => vec![x, y, z, width, height, depth],
+            Command::Torus { x, y, z, inner_radius, outer_radius, .. } => vec![x, y, z, inner_radius, outer_radius],
+            Command::Line { x0, y0, z0, x1, y1, z1 } => vec![x0, y0, z0, x1, y1, z1],
+            Command::Circle { x, y, z, radius } => vec![x, y, z, radius],
+            Command::Curve { points, .. } => points.iter().collect(),
+            Command::Detail(Detail::Steps(steps)) => vec![steps],
+            Command::LineWidth(width) => vec![width],
+            Command::If { condition, .. } => {
+                let mut values = vec![&condition.left];
+                values.extend(condition.comparison.as_ref().map(|(_, right)| right));
+                values
+            }
+            _ => Vec::new(),
+        }
+    }
+}
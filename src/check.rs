@@ -0,0 +1,155 @@
+//! Looks over a parsed script for mistakes that would otherwise only show up partway
+//! through a render, so they can all be reported at once before anything is drawn.
+
+use crate::ast::{Background, Command, Name, Script, Statement, Value};
+use crate::error::MdlError;
+use shunting::{MathContext, MathOp};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// every problem found in the script, those with the frames and varies first and then the
+/// rest in the order they would run
+pub fn check(script: &Script) -> Vec<MdlError> {
+    let mut problems = Vec::new();
+    check_settings(script, &mut problems);
+    check_statements(script, &mut problems);
+    problems
+}
+
+fn check_settings(script: &Script, problems: &mut Vec<MdlError>) {
+    let frame_total = script.settings.frames.as_ref().map(|frames| frames.node);
+    if frame_total == Some(0) {
+        let frames = script.settings.frames.as_ref().unwrap();
+        problems.push(MdlError::with_span("there must be at least one frame", &frames.span));
+    }
+    for vary in &script.varies {
+        match frame_total {
+            None => problems.push(MdlError::with_span("vary used without frame numbers included", &vary.span)),
+            Some(frame_total) if vary.node.end_frame >= frame_total => problems.push(MdlError::with_span(
+                format!("vary ends at frame {} but there are only {} frames", vary.node.end_frame, frame_total),
+                &vary.span,
+            )),
+            _ => {}
+        }
+    }
+    if let Some(background) = &script.settings.background {
+        if let Background::Picture(filename) = &background.node {
+            if !Path::new(filename).is_file() {
+                problems.push(MdlError::with_span(format!("background image {} does not exist", filename), &background.span));
+            }
+        }
+    }
+}
+
+/// what is known at each point while following the statements in order
+struct Walk<'a> {
+    knobs: HashSet<&'a str>,
+    /// every knob set to 0, which is enough to find expressions using names that are never set
+    variables: MathContext,
+    constants: HashSet<String>,
+    coord_systems: HashSet<String>,
+    /// how many pushes have not been popped yet
+    depth: usize,
+}
+
+/// follows the statements in order, since constants and coordinate systems only exist once
+/// the command making them has run
+fn check_statements(script: &Script, problems: &mut Vec<MdlError>) {
+    let knobs: HashSet<&str> = script
+        .varies
+        .iter()
+        .map(|vary| vary.node.knob.as_str())
+        .chain(script.variables.iter().map(|variable| variable.node.name.as_str()))
+        .collect();
+    let variables = MathContext::new();
+    for knob in knobs.iter().chain(&["frame_num"]) {
+        variables.setvar(knob, MathOp::Number(0.0));
+    }
+    let mut walk = Walk {
+        knobs,
+        variables,
+        constants: HashSet::new(),
+        coord_systems: HashSet::new(),
+        depth: 0,
+    };
+    walk.statements(&script.statements, problems);
+}
+
+impl Walk<'_> {
+    fn statements(&mut self, statements: &[Statement], problems: &mut Vec<MdlError>) {
+        for statement in statements {
+            self.statement(statement, problems);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, problems: &mut Vec<MdlError>) {
+        for value in statement.node.values() {
+            if let Value::Expression(expression) = value {
+                if let Err(error) = self.variables.eval(&expression.rpn) {
+                    if let Some(name) = error.strip_prefix("Unknown Variable: ") {
+                        problems.push(MdlError::with_span(format!("{} in {} is not a knob or a variable", name, expression.text), &expression.span));
+                    }
+                }
+            }
+        }
+        match &statement.node {
+            Command::Constants { name, knob, .. } => {
+                self.check_knob(knob, problems);
+                self.constants.insert(name.clone());
+            }
+            Command::Move { knob, .. } | Command::Rotate { knob, .. } | Command::Scale { knob, .. } => {
+                self.check_knob(knob, problems);
+            }
+            Command::Push(coord_system) => {
+                check_name(&self.coord_systems, coord_system, "coordinate system", problems);
+                self.depth += 1;
+            }
+            Command::Pop => {
+                if self.depth == 0 {
+                    problems.push(MdlError::with_span("pop without a matching push", &statement.span));
+                } else {
+                    self.depth -= 1;
+                }
+            }
+            Command::Sphere { constants, coord_system, .. } | Command::Box { constants, coord_system, .. } | Command::Torus { constants, coord_system, .. } => {
+                check_name(&self.constants, constants, "constants", problems);
+                check_name(&self.coord_systems, coord_system, "coordinate system", problems);
+            }
+            Command::SaveCoordSystem(name) => {
+                self.coord_systems.insert(name.clone());
+            }
+            Command::If { then_statements, else_statements, .. } => {
+                // names made in either part count afterwards, but both parts have to leave the
+                // stack as deep as each other or later pops depend on the frame
+                let depth = self.depth;
+                self.statements(then_statements, problems);
+                let then_depth = self.depth;
+                self.depth = depth;
+                self.statements(else_statements, problems);
+                if self.depth != then_depth {
+                    problems.push(MdlError::with_span("the parts of this if push and pop a different number of times", &statement.span));
+                }
+                self.depth = self.depth.max(then_depth);
+            }
+            _ => {}
+        }
+    }
+
+    /// reports a knob that would stay 0 in every frame since nothing gives it a value
+    fn check_knob(&self, knob: &Option<Name>, problems: &mut Vec<MdlError>) {
+        if let Some(knob) = knob {
+            if !self.knobs.contains(knob.node.as_str()) {
+                problems.push(MdlError::with_span(format!("knob {} is never set by a vary or set", knob.node), &knob.span));
+            }
+        }
+    }
+}
+
+/// reports a reference to constants or a coordinate system that does not exist yet
+fn check_name(names: &HashSet<String>, name: &Option<Name>, kind: &str, problems: &mut Vec<MdlError>) {
+    if let Some(name) = name {
+        if !names.contains(&name.node) {
+            problems.push(MdlError::with_span(format!("no {} named {} has been defined before this", kind, name.node), &name.span));
+        }
+    }
+}
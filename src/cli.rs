@@ -0,0 +1,181 @@
+//! Command line options for the renderer binary.
+
+use crate::image::Preview;
+use crate::log::Verbosity;
+use std::thread;
+
+pub const USAGE: &str = "usage: final-project [options] <script.mdl>
+       final-project [options] repl    type commands in one at a time, :help for more
+
+options:
+    -o, --output-dir <dir>    where animation frames and stills are written (default animation)
+    -s, --size <width>x<height>
+                              canvas size in pixels (default 500x500)
+    -f, --frames <n>|<a>-<b>  only render frame n, or frames a through b
+    -t, --format ppm|png      file format of rendered frames (default ppm)
+    -p, --preview auto|window|terminal|sixel
+                              where display shows the picture, auto uses the terminal
+                              when there is no program to open a window (default auto)
+    -j, --jobs <n>            threads rendering animation frames at once, or filling in the
+                              triangles of a still (default the number of cores)
+        --still               render one still image instead of an animation
+        --check               only look for mistakes in the script, without rendering
+        --watch               render the still, or the first frame given with -f, again
+                              whenever the script or a file it reads changes
+    -q, --quiet               only print errors
+    -v, --verbose             also print timings and knob values
+    -h, --help                print this message";
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OutputFormat {
+    Ppm,
+    Png,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Ppm => "ppm",
+            OutputFormat::Png => "png",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub script: String,
+    pub output_dir: String,
+    pub width: usize,
+    pub height: usize,
+    /// first and last frame to render, inclusive
+    pub frame_range: Option<(usize, usize)>,
+    pub format: OutputFormat,
+    pub preview: Preview,
+    /// threads rendering animation frames, or filling in a still
+    pub jobs: usize,
+    pub verbosity: Verbosity,
+    pub still_only: bool,
+    /// stop after validating the script
+    pub check_only: bool,
+    /// keep rendering the still every time the script changes
+    pub watch: bool,
+    /// read commands from the terminal instead of a script
+    pub repl: bool,
+    pub help: bool,
+}
+
+impl Options {
+    pub fn new(script: &str) -> Options {
+        Options {
+            script: script.to_owned(),
+            output_dir: String::from("animation"),
+            width: 500,
+            height: 500,
+            frame_range: None,
+            format: OutputFormat::Ppm,
+            preview: Preview::Auto,
+            jobs: thread::available_parallelism().map_or(1, |cores| cores.get()),
+            verbosity: Verbosity::Normal,
+            still_only: false,
+            check_only: false,
+            watch: false,
+            repl: false,
+            help: false,
+        }
+    }
+}
+
+/// reads the options from the arguments after the program name
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::new("");
+    let mut script = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .map(|value| value.as_str())
+                .ok_or(format!("{} needs a value", name))
+        };
+        match arg.as_str() {
+            "-o" | "--output-dir" => options.output_dir = value(arg)?.to_owned(),
+            "-s" | "--size" => {
+                let size = value(arg)?;
+                let (width, height) = size
+                    .split_once('x')
+                    .ok_or(format!("size should look like 500x500, not {}", size))?;
+                options.width = parse_positive(width, "width")?;
+                options.height = parse_positive(height, "height")?;
+            }
+            "-f" | "--frames" => {
+                let frames = value(arg)?;
+                options.frame_range = Some(match frames.split_once('-') {
+                    Some((first, last)) => {
+                        let first = parse_number(first, "first frame")?;
+                        let last = parse_number(last, "last frame")?;
+                        if last < first {
+                            return Err(format!("the frame range {} ends before it starts", frames));
+                        }
+                        (first, last)
+                    }
+                    None => {
+                        let frame = parse_number(frames, "frame")?;
+                        (frame, frame)
+                    }
+                });
+            }
+            "-t" | "--format" => {
+                options.format = match value(arg)? {
+                    "ppm" => OutputFormat::Ppm,
+                    "png" => OutputFormat::Png,
+                    format => return Err(format!("unknown output format {}, please use ppm or png", format)),
+                }
+            }
+            "-p" | "--preview" => {
+                options.preview = match value(arg)? {
+                    "auto" => Preview::Auto,
+                    "window" => Preview::Window,
+                    "terminal" => Preview::Terminal,
+                    "sixel" => Preview::Sixel,
+                    preview => return Err(format!("unknown preview {}, please use auto, window, terminal or sixel", preview)),
+                }
+            }
+            "-j" | "--jobs" => options.jobs = parse_positive(value(arg)?, "jobs")?,
+            "--still" => options.still_only = true,
+            "--check" => options.check_only = true,
+            "--watch" => options.watch = true,
+            "-q" | "--quiet" => options.verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => options.verbosity = Verbosity::Verbose,
+            "-h" | "--help" => options.help = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option {}", arg)),
+            "repl" if script.is_none() && !options.repl => options.repl = true,
+            _ => {
+                if options.repl {
+                    return Err(format!("the repl does not take a script, but {} was given", arg));
+                }
+                if script.is_some() {
+                    return Err(format!("only one script can be rendered at a time, but {} was also given", arg));
+                }
+                script = Some(arg.to_owned());
+            }
+        }
+    }
+    match script {
+        Some(script) => options.script = script,
+        None if options.help || options.repl => {}
+        None => return Err(String::from("no script given")),
+    }
+    Ok(options)
+}
+
+fn parse_number(text: &str, name: &str) -> Result<usize, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{} should be a whole number, not {}", name, text))
+}
+
+fn parse_positive(text: &str, name: &str) -> Result<usize, String> {
+    match parse_number(text, name)? {
+        0 => Err(format!("{} must be more than 0", name)),
+        number => Ok(number),
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct Color{
     pub r: u8,
     pub g: u8,
@@ -21,16 +21,73 @@ impl Color{
         self.g = new_color.g;
         self.b = new_color.b;
     }
-    
-    pub fn reset_color(&mut self){
-        self.r = 0;
-        self.b = 0;
-        self.g = 0;
-    }
 }
 
 impl fmt::Display for Color{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
         write!(f, "{} {} {}", self.r, self.g, self.b)
     }
-}
\ No newline at end of file
+}
+
+/// a color in linear light, where 1.0 is the brightest a Color channel can show
+/// and brighter values are kept instead of clamped
+#[derive(Copy, Clone, Debug)]
+pub struct LinearColor{
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl LinearColor{
+    pub const fn new_values(r: f32, g: f32, b: f32) -> LinearColor{
+        LinearColor{r, g, b}
+    }
+
+    /// treats each channel as already linear, so to_color gives back the same color
+    pub fn from_color(color: &Color) -> LinearColor{
+        LinearColor{r: color.r as f32 / 255.0, g: color.g as f32 / 255.0, b: color.b as f32 / 255.0}
+    }
+
+    /// decodes a gamma encoded sRGB color into linear light
+    pub fn from_srgb(color: &Color) -> LinearColor{
+        LinearColor{r: srgb_to_linear(color.r), g: srgb_to_linear(color.g), b: srgb_to_linear(color.b)}
+    }
+
+    /// clamps each channel to 0-1 without any gamma
+    pub fn to_color(self) -> Color{
+        Color::new_color(
+            (self.r.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (self.g.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (self.b.clamp(0.0, 1.0) * 255.0).round() as u8,
+        )
+    }
+
+    /// linear interpolation towards other, where amount 0 is self and 1 is other
+    pub fn mix(&self, other: &LinearColor, amount: f32) -> LinearColor{
+        LinearColor{
+            r: self.r + (other.r - self.r) * amount,
+            g: self.g + (other.g - self.g) * amount,
+            b: self.b + (other.b - self.b) * amount,
+        }
+    }
+}
+
+pub fn srgb_to_linear(channel: u8) -> f32{
+    let value = channel as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// encodes a linear value from 0 to 1 with the sRGB transfer curve
+pub fn linear_to_srgb(value: f32) -> u8{
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
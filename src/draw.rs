@@ -1,15 +1,20 @@
 use crate::ReflectionValue;
 use crate::Color;
-use crate::CurveType;
+use crate::color::LinearColor;
+use crate::matrix::{CurveType, Mat4, VertexBuffer};
+use crate::mesh::Mesh;
 use crate::Image;
-use crate::Matrix;
+use crate::image::{LineStyle, Shading};
 use crate::gmath::*;
 use std::f32;
+use std::sync::Mutex;
+use std::thread;
 
 impl Image {
-    pub fn draw_line(&mut self, mut x0: i32, mut y0: i32, mut z0: f32, mut x1: i32, mut y1: i32, mut z1: f32, color: &Color) {
+    pub fn draw_line(&mut self, mut x0: i32, mut y0: i32, mut z0: f32, mut x1: i32, mut y1: i32, mut z1: f32, color: &LinearColor) {
         // println!("x0: {}, y0: {}, x1: {}, y1: {}", x0, y0, x1, y1);
-        if (x0 >= 500 &&  x1 >= 500) || (y0 >= 500 && y1 >= 500) || (x0 < 0 &&  x1 < 0) || (y0 < 0 && y1 < 0){
+        let (width, height) = (self.width as i32, self.height as i32);
+        if (x0 >= width &&  x1 >= width) || (y0 >= height && y1 >= height) || (x0 < 0 &&  x1 < 0) || (y0 < 0 && y1 < 0){
             return;
         }
         if x0 > x1 {
@@ -119,17 +124,79 @@ impl Image {
         }
     }
 
-    pub fn draw_lines(&mut self, matrix: &Matrix, color: &Color) {
-        for i in (0..matrix.matrix_array[0].len()).step_by(2) {
-            self.draw_line(
-                matrix.matrix_array[0][i] as i32,
-                matrix.matrix_array[1][i] as i32,
-                matrix.matrix_array[2][i] as f32,
-                matrix.matrix_array[0][i + 1] as i32,
-                matrix.matrix_array[1][i + 1] as i32,
-                matrix.matrix_array[2][i + 1] as f32,
-                color,
-            );
+    pub fn draw_lines(&mut self, edges: &VertexBuffer, color: &Color) {
+        let color = &self.paint(color);
+        for edge in edges.points.chunks_exact(2) {
+            self.draw_stroke(&edge[0], &edge[1], color);
+        }
+    }
+
+    /// draws a line between two points given in output pixel coordinates using the current
+    /// line style and width. Only a sharp line one sample wide can use Bresenham, since with
+    /// supersampling a width of 1 covers several samples
+    fn draw_stroke(&mut self, start: &[f32; 4], end: &[f32; 4], color: &LinearColor) {
+        if self.line_style == LineStyle::Sharp && self.line_width == 1.0 && self.supersample == 1 {
+            self.draw_line(start[0] as i32, start[1] as i32, start[2], end[0] as i32, end[1] as i32, end[2], color);
+        } else {
+            let stroke = Stroke {
+                start: [self.to_line_sample(start[0]), self.to_line_sample(start[1]), start[2]],
+                end: [self.to_line_sample(end[0]), self.to_line_sample(end[1]), end[2]],
+                width: self.to_sample(self.line_width),
+                style: self.line_style,
+            };
+            self.draw_wide_line(&stroke, color);
+        }
+    }
+
+    /// draws a stroke by walking the major axis one sample at a time and covering a span
+    /// across the minor axis.
+    ///
+    /// With the smooth style each pixel of the span is blended by how much of it the span
+    /// covers, which for a width of 1 is Xiaolin Wu's algorithm. With the sharp style only
+    /// pixels whose centers fall in the span are plotted.
+    pub fn draw_wide_line(&mut self, stroke: &Stroke, color: &LinearColor) {
+        let [x0, y0, z0] = stroke.start;
+        let [x1, y1, z1] = stroke.end;
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        // work as if x is the major axis, swapping back when plotting
+        let (mut x0, mut y0, mut z0, mut x1, mut y1, mut z1) = if steep {
+            (y0, x0, z0, y1, x1, z1)
+        } else {
+            (x0, y0, z0, x1, y1, z1)
+        };
+        if x0 > x1 {
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
+            std::mem::swap(&mut z0, &mut z1);
+        }
+        let dx = x1 - x0;
+        let gradient = if dx == 0.0 { 0.0 } else { (y1 - y0) / dx };
+        let z_rate = if dx == 0.0 { 0.0 } else { (z1 - z0) / dx };
+        // the span across the minor axis is wider than the line when the line is slanted
+        let half_span = stroke.width * (1.0 + gradient * gradient).sqrt() / 2.0;
+        let major_limit = if steep { self.height } else { self.width } as f32;
+        let start = x0.round().max(0.0);
+        let stop = x1.round().min(major_limit - 1.0);
+        let mut major = start;
+        while major <= stop {
+            let center = y0 + gradient * (major - x0);
+            let z = z0 + z_rate * (major - x0);
+            let low = center - half_span;
+            let high = center + half_span;
+            for minor in (low.round() as i32)..=(high.round() as i32) {
+                // pixel minor covers [minor - 0.5, minor + 0.5] across the minor axis
+                let coverage = (high.min(minor as f32 + 0.5) - low.max(minor as f32 - 0.5)).clamp(0.0, 1.0);
+                let (x, y) = if steep { (minor, major as i32) } else { (major as i32, minor) };
+                match stroke.style {
+                    LineStyle::Smooth => self.blend(x, y, z, color, coverage),
+                    LineStyle::Sharp => {
+                        if (minor as f32) >= low && (minor as f32) < high {
+                            self.plot(x, y, z, color);
+                        }
+                    }
+                }
+            }
+            major += 1.0;
         }
     }
 
@@ -143,130 +210,238 @@ impl Image {
     ///
     ///Returns:
     ///
-    ///Goes through polygons 3 points at a time, drawing
-    ///lines connecting each points to create bounding triangles
+    ///Goes through the triangles of the mesh, lighting and filling
+    ///in the ones facing the viewer. With gouraud shading each
+    ///corner is lit with its own normal and the colors are blended
+    ///across the triangle
     ///====================
-    pub fn draw_polygons(&mut self, polygons: &Matrix, c: &Color, view: &mut Vec<f32>, ambient_color: &Color, point_light_vector: &mut Vec<f32>, point_light_color: &Color, ambient_reflect: &ReflectionValue, direct_reflect: &ReflectionValue, specular_reflect: &ReflectionValue) {
-        for i in (0..polygons.matrix_array[0].len()).step_by(3) {
+    pub fn draw_polygons(&mut self, polygons: &Mesh, c: &Color, view: &mut [f32; 3], ambient_color: &Color, point_light_vector: &mut [f32; 3], point_light_color: &Color, ambient_reflect: &ReflectionValue, direct_reflect: &ReflectionValue, specular_reflect: &ReflectionValue) {
+        let hdr = self.hdr_screen.is_some();
+        normalize(view);
+        normalize(point_light_vector);
+        let linear_lighting = LinearLighting {
+            view: *view,
+            ambient_light: LinearColor::from_srgb(ambient_color),
+            point_light_color: LinearColor::from_srgb(point_light_color),
+            point_light_vector: *point_light_vector,
+            ambient_reflect,
+            diffuse_reflect: direct_reflect,
+            specular_reflect,
+        };
+        let mut light = |normal: &mut [f32; 3]| {
+            if hdr {
+                get_lighting_linear(normal, &linear_lighting)
+            } else {
+                LinearColor::from_color(&get_lighting(normal, view, ambient_color, point_light_color, point_light_vector, ambient_reflect, direct_reflect, specular_reflect))
+            }
+        };
+        let vertex_colors: Option<Vec<LinearColor>> = match self.shading {
+            Shading::Gouraud => Some(polygons.vertex_normals().iter_mut().map(&mut light).collect()),
+            _ => None,
+        };
+        let mut triangles = Vec::new();
+        for (i, corners) in polygons.triangles.iter().enumerate() {
             let normal = &mut polygons.calculate_normal(i);
             if normal[2] > 0.0 {
-                // self.draw_line(
-                //     polygons.matrix_array[0][i] as i32,
-                //     polygons.matrix_array[1][i] as i32,
-                //     polygons.matrix_array[2][i] as f32,
-                //     polygons.matrix_array[0][i + 1] as i32,
-                //     polygons.matrix_array[1][i + 1] as i32,
-                //     polygons.matrix_array[2][i + 1] as f32,
-                //     c,
-                // );
-                // self.draw_line(
-                //     polygons.matrix_array[0][i + 1] as i32,
-                //     polygons.matrix_array[1][i + 1] as i32,
-                //     polygons.matrix_array[2][i + 1] as f32,
-                //     polygons.matrix_array[0][i + 2] as i32,
-                //     polygons.matrix_array[1][i + 2] as i32,
-                //     polygons.matrix_array[2][i + 2] as f32,
-                //     c,
-                // );
-                // self.draw_line(
-                //     polygons.matrix_array[0][i + 2] as i32,
-                //     polygons.matrix_array[1][i + 2] as i32,
-                //     polygons.matrix_array[2][i + 2] as f32,
-                //     polygons.matrix_array[0][i] as i32,
-                //     polygons.matrix_array[1][i] as i32,
-                //     polygons.matrix_array[2][i] as f32,
-                //     c,
-                // );
-                let color = &get_lighting(normal, view, ambient_color, point_light_color, point_light_vector, ambient_reflect, direct_reflect, specular_reflect);
-                self.scanline_convert(
-                    polygons.matrix_array[0][i],
-                    polygons.matrix_array[1][i],
-                    polygons.matrix_array[2][i],
-                    polygons.matrix_array[0][i + 1],
-                    polygons.matrix_array[1][i + 1],
-                    polygons.matrix_array[2][i + 1],
-                    polygons.matrix_array[0][i + 2],
-                    polygons.matrix_array[1][i + 2],
-                    polygons.matrix_array[2][i + 2],
-                    color
-                )
+                let corners = corners.map(|corner| corner as usize);
+                if self.shading == Shading::Wireframe {
+                    let c = &self.paint(c);
+                    for (start, end) in [(corners[0], corners[1]), (corners[1], corners[2]), (corners[2], corners[0])] {
+                        self.draw_stroke(&polygons.vertices[start], &polygons.vertices[end], c);
+                    }
+                    continue;
+                }
+                let colors = match &vertex_colors {
+                    Some(vertex_colors) => corners.map(|corner| vertex_colors[corner]),
+                    None => [light(normal); 3],
+                };
+                let vertex = |index: usize| {
+                    let point = polygons.vertices[index];
+                    (self.to_sample(point[0]), self.to_sample(point[1]), point[2])
+                };
+                triangles.push((corners.map(vertex), colors));
             }
         }
+        self.fill_triangles(&triangles);
     }
 
-    /*======== void scanline_convert() ==========
-    Inputs: x0 y0 z0 x1 y1 z1 x2 y2 z2: i32
-            self screen
-    Returns:
-
-    Fills in polygon i by drawing consecutive horizontal (or vertical) lines.
-
-    Color should be set differently for each polygon.
-    ====================*/
-    fn scanline_convert(&mut self, x0: f32, y0: f32, z0: f32, x1: f32, y1: f32, z1: f32, x2: f32, y2: f32, z2: f32, color: &Color) {
-        let mut polygons = [(x0, y0, z0), (x1, y1, z1), (x2, y2, z2)];
-        polygons.sort_by_key(|k| (k.1 as i32, k.0 as i32, k.2 as i32));
-        // println!("{:?}", polygons);
-        let mut past_midpoint = false;
-        let mut x0 = polygons[0].0;
-        let mut x1 = polygons[0].0;
-        let mut z0 = polygons[0].2;
-        let mut z1 = polygons[0].2;
-        let d0 = (polygons[2].1 as i32 - polygons[0].1 as i32) + 1;
-        let d1 = (polygons[1].1 as i32 - polygons[0].1 as i32) + 1;
-        let d2 = (polygons[2].1 as i32 - polygons[1].1 as i32) + 1;
-        let dx0 = if d0 > 0 {(polygons[2].0 - polygons[0].0) / d0 as f32} else {0.0};
-        let dz0 = if d0 > 0 {(polygons[2].2 - polygons[0].2) / d0 as f32} else {0.0};
-        let mut dx1 = if d1 > 0 {(polygons[1].0 - polygons[0].0) / d1 as f32} else {0.0};
-        let mut dz1 = if d1 > 0 {(polygons[1].2 - polygons[0].2) / d1 as f32} else {0.0};
-        let dx1_1 = if d2 > 0 {(polygons[2].0 - polygons[1].0) / d2 as f32} else {0.0};
-        let dz1_1 = if d2 > 0 {(polygons[2].2 - polygons[1].2) / d2 as f32} else {0.0};
-        if (polygons[2].1 - polygons[1].1) as i32 == 0{
-            past_midpoint = true
+    /// fills the triangles in order, splitting the screen into bands of rows that are filled
+    /// on separate threads when there are enough triangles to be worth it. Each band only
+    /// gets the triangles reaching into it, still in order, so every pixel ends up the same
+    /// as filling them one after another
+    fn fill_triangles(&mut self, triangles: &[Triangle]) {
+        let height = self.height;
+        let width = self.width as i32;
+        let threads = if triangles.len() >= MIN_PARALLEL_TRIANGLES { self.threads } else { 1 };
+        let band_rows = if threads > 1 { BAND_ROWS } else { height.max(1) };
+        let band_count = height.div_ceil(band_rows);
+        let mut bins: Vec<Vec<&Triangle>> = vec![Vec::new(); band_count];
+        for triangle in triangles {
+            let (low, high) = triangle.0.iter().fold((i32::MAX, i32::MIN), |(low, high), vertex| (low.min(vertex.1.floor() as i32), high.max(vertex.1.floor() as i32)));
+            // y counts up from the bottom of the screen while rows count down from the top
+            let first_row = (height as i32 - 1 - high).max(0) as usize;
+            let last_row = height as i32 - 1 - low;
+            if last_row < 0 || first_row >= height {
+                continue;
+            }
+            for bin in &mut bins[first_row / band_rows..=(last_row as usize).min(height - 1) / band_rows] {
+                bin.push(triangle);
+            }
         }
-        // for y in y0 as i32..=y0 as i32+1 {
-        for y in polygons[0].1 as i32..=polygons[2].1 as i32{
-            if y >= polygons[1].1 as i32 && !past_midpoint{
-                dx1 = dx1_1;
-                dz1 = dz1_1;
-                x1 = polygons[1].0;
-                z1 = polygons[1].2;
-                past_midpoint = true;
+        let mut hdr_rows = self.hdr_screen.as_mut().map(|hdr_screen| hdr_screen.chunks_mut(band_rows));
+        let bands: Vec<(Band, Vec<&Triangle>)> = self
+            .screen
+            .chunks_mut(band_rows)
+            .zip(self.z_buffer.chunks_mut(band_rows))
+            .enumerate()
+            .map(|(index, (screen, z_buffer))| Band {
+                first_row: index * band_rows,
+                screen,
+                z_buffer,
+                hdr_screen: hdr_rows.as_mut().and_then(|rows| rows.next()),
+                width,
+                height,
+            })
+            .zip(bins)
+            .filter(|(_, bin)| !bin.is_empty())
+            .collect();
+        let fill = |(mut band, bin): (Band, Vec<&Triangle>)| {
+            for triangle in bin {
+                band.fill(triangle);
             }
-            self.draw_line(x0 as i32, y, z0, x1 as i32, y, z1, color);
-            x0 += dx0;
-            x1 += dx1;
-            z0 += dz0;
-            z1 += dz1;
+        };
+        if threads <= 1 {
+            bands.into_iter().for_each(fill);
+            return;
         }
+        let bands = Mutex::new(bands.into_iter());
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    // the lock is only held while taking the next band
+                    let band = bands.lock().unwrap().next();
+                    match band {
+                        Some(band) => fill(band),
+                        None => break,
+                    }
+                });
+            }
+        });
     }
 }
 
-impl Matrix {
-    pub fn add_edge(&mut self, x0: f32, y0: f32, z0: f32, x1: f32, y1: f32, z1: f32) {
-        if self.matrix_array.len() < 4 {
-            *self = Matrix::new(4, 0);
-        }
-        self.add_point(x0, y0, z0);
-        self.add_point(x1, y1, z1);
-    }
+/// a line between two points in screen samples, with its width in screen samples and how its
+/// edges are drawn
+#[derive(Clone, Copy, Debug)]
+pub struct Stroke {
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    pub width: f32,
+    pub style: LineStyle,
+}
+
+/// three corners in screen samples and the lit color of each, which are all the same
+/// unless the shading blends them
+type Triangle = ([(f32, f32, f32); 3], [LinearColor; 3]);
+
+/// rows of screen per band when filling on several threads
+const BAND_ROWS: usize = 16;
+/// fewer triangles than this are filled on the calling thread, since starting threads
+/// would take longer than filling them
+const MIN_PARALLEL_TRIANGLES: usize = 256;
+/// corners are snapped to 1 / 2^SUBPIXEL_BITS of a sample, so edges can be tested exactly
+/// with integers
+const SUBPIXEL_BITS: u32 = 4;
+/// triangles with a corner further than this many samples from the origin are left out, since
+/// their edge functions would overflow
+const MAX_COORDINATE: f32 = (1 << 24) as f32;
+
+/// twice the signed area of the triangle a b p, which is positive when p is to the left of the
+/// line from a to b
+fn edge_function(a: (i64, i64), b: (i64, i64), p: (i64, i64)) -> i64 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+/// whether pixel centres exactly on the edge from a to b belong to the triangle, given its
+/// corners go counter clockwise. Only left edges and top edges count, so of two triangles
+/// sharing an edge exactly one of them fills the pixels on it
+fn is_top_left(a: (i64, i64), b: (i64, i64)) -> bool {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    // y counts up the screen, so a left edge goes down and a top edge goes left
+    dy < 0 || (dy == 0 && dx < 0)
+}
+
+/// a run of whole rows of the canvas, which one thread can fill in on its own
+struct Band<'a> {
+    /// the row of the screen the band starts at
+    first_row: usize,
+    screen: &'a mut [Vec<Color>],
+    z_buffer: &'a mut [Vec<f32>],
+    hdr_screen: Option<&'a mut [Vec<LinearColor>]>,
+    width: i32,
+    /// of the whole screen
+    height: usize,
+}
 
-    pub fn add_edge_int(&mut self, x0: i32, y0: i32, z0: i32, x1: i32, y1: i32, z1: i32) {
-        if self.matrix_array.len() < 4 {
-            *self = Matrix::new(4, 0);
+impl Band<'_> {
+    /// fills the pixels of the band whose centres are inside the triangle, with depth and
+    /// color blended between the corners
+    fn fill(&mut self, (corners, colors): &Triangle) {
+        // written so nan fails too
+        if !corners.iter().all(|corner| corner.0.abs() < MAX_COORDINATE && corner.1.abs() < MAX_COORDINATE) {
+            return;
+        }
+        let one = 1i64 << SUBPIXEL_BITS;
+        let fixed = corners.map(|(x, y, _)| ((x * one as f32).round() as i64, (y * one as f32).round() as i64));
+        let area = edge_function(fixed[0], fixed[1], fixed[2]);
+        // facing away, or too thin to cover any pixel centre once snapped
+        if area <= 0 {
+            return;
+        }
+        // each corner's weight comes from the edge across from it
+        let edges = [(fixed[1], fixed[2]), (fixed[2], fixed[0]), (fixed[0], fixed[1])];
+        let bias = edges.map(|(a, b)| if is_top_left(a, b) { 0 } else { 1 });
+        let lowest = |axis: fn(&(i64, i64)) -> i64| fixed.iter().map(axis).min().unwrap() >> SUBPIXEL_BITS;
+        let highest = |axis: fn(&(i64, i64)) -> i64| fixed.iter().map(axis).max().unwrap() >> SUBPIXEL_BITS;
+        let (band_bottom, band_top) = ((self.height - self.first_row - self.screen.len()) as i64, (self.height - 1 - self.first_row) as i64);
+        let (left, right) = (lowest(|corner| corner.0).max(0), highest(|corner| corner.0).min(self.width as i64 - 1));
+        let (bottom, top) = (lowest(|corner| corner.1).max(band_bottom), highest(|corner| corner.1).min(band_top));
+        let area = area as f64;
+        let depths = corners.map(|corner| corner.2 as f64);
+        let color_rates = [colors[1] - colors[0], colors[2] - colors[0]];
+        for y in bottom..=top {
+            let row = self.height - 1 - y as usize - self.first_row;
+            // pixel centres are half a pixel in from their corner
+            let centre = (left * one + one / 2, y * one + one / 2);
+            let mut weights = edges.map(|(a, b)| edge_function(a, b, centre));
+            let steps = edges.map(|(a, b)| (a.1 - b.1) * one);
+            for x in left..=right {
+                if (0..3).all(|corner| weights[corner] >= bias[corner]) {
+                    let [w0, w1, w2] = weights.map(|weight| weight as f64 / area);
+                    let depth = (depths[0] * w0 + depths[1] * w1 + depths[2] * w2) as f32;
+                    let v = x as usize;
+                    if depth >= self.z_buffer[row][v] {
+                        // added onto the first color so flat triangles keep theirs exactly
+                        let color = colors[0] + color_rates[0] * w1 as f32 + color_rates[1] * w2 as f32;
+                        match &mut self.hdr_screen {
+                            Some(hdr_screen) => hdr_screen[row][v] = color,
+                            None => self.screen[row][v] = color.to_color(),
+                        }
+                        self.z_buffer[row][v] = depth;
+                    }
+                }
+                for (weight, step) in weights.iter_mut().zip(steps) {
+                    *weight += step;
+                }
+            }
         }
-        self.add_point(x0 as f32, y0 as f32, z0 as f32);
-        self.add_point(x1 as f32, y1 as f32, z1 as f32);
     }
+}
 
-    pub fn add_point(&mut self, x: f32, y: f32, z: f32) {
-        if self.matrix_array.len() < 4 {
-            *self = Matrix::new(4, 0);
-        }
-        self.matrix_array[0].push(x);
-        self.matrix_array[1].push(y);
-        self.matrix_array[2].push(z);
-        self.matrix_array[3].push(1.0);
+impl VertexBuffer {
+    pub fn add_edge(&mut self, x0: f32, y0: f32, z0: f32, x1: f32, y1: f32, z1: f32) {
+        self.add_point(x0, y0, z0);
+        self.add_point(x1, y1, z1);
     }
 
     pub fn add_circle(&mut self, cx: f32, cy: f32, cz: f32, r: f32, step: i32) {
@@ -295,186 +470,25 @@ impl Matrix {
         step: i32,
         curve_type: &CurveType,
     ) {
-        let matrix_x = Matrix::generate_curve_coefs(x0, x1, x2, x3, curve_type);
-        let matrix_y = Matrix::generate_curve_coefs(y0, y1, y2, y3, curve_type);
+        let coefs_x = Mat4::generate_curve_coefs(x0, x1, x2, x3, curve_type);
+        let coefs_y = Mat4::generate_curve_coefs(y0, y1, y2, y3, curve_type);
         let mut prev_x = x0;
         let mut prev_y = y0;
         for t in 0..step + 1 {
-            let x = (matrix_x.matrix_array[0][0] * (t as f32 / step as f32).powi(3))
-                + (matrix_x.matrix_array[1][0] * (t as f32 / step as f32).powi(2))
-                + (matrix_x.matrix_array[2][0] * t as f32 / step as f32)
-                + matrix_x.matrix_array[3][0];
-            let y = (matrix_y.matrix_array[0][0] * (t as f32 / step as f32).powi(3))
-                + (matrix_y.matrix_array[1][0] * (t as f32 / step as f32).powi(2))
-                + (matrix_y.matrix_array[2][0] * t as f32 / step as f32)
-                + matrix_y.matrix_array[3][0];
+            let x = (coefs_x[0] * (t as f32 / step as f32).powi(3))
+                + (coefs_x[1] * (t as f32 / step as f32).powi(2))
+                + (coefs_x[2] * t as f32 / step as f32)
+                + coefs_x[3];
+            let y = (coefs_y[0] * (t as f32 / step as f32).powi(3))
+                + (coefs_y[1] * (t as f32 / step as f32).powi(2))
+                + (coefs_y[2] * t as f32 / step as f32)
+                + coefs_y[3];
             self.add_edge(prev_x, prev_y, 0.0, x, y, 0.0);
             prev_x = x;
             prev_y = y;
         }
     }
 
-    /// add_box()
-    /// Inputs:   matrix * edges
-    ///
-    ///             double x
-    ///
-    ///             double y
-    ///
-    ///             double z
-    ///
-    ///             double width
-    ///
-    ///             double height
-    ///
-    ///             double depth
-
-    /// add the points for a rectagular prism whose
-    /// upper-left-front corner is (x, y, z) with width,
-    /// height and depth dimensions.
-    pub fn add_box(&mut self, x: f32, y: f32, z: f32, width: f32, height: f32, depth: f32) {
-        // front
-        self.add_polygon(x + width, y - height, z, x + width, y, z, x, y, z);
-        self.add_polygon(x + width, y - height, z, x, y, z, x, y - height, z);
-        // right
-        self.add_polygon(
-            x + width,
-            y,
-            z,
-            x + width,
-            y - height,
-            z - depth,
-            x + width,
-            y,
-            z - depth,
-        );
-        self.add_polygon(
-            x + width,
-            y - height,
-            z,
-            x + width,
-            y - height,
-            z - depth,
-            x + width,
-            y,
-            z,
-        );
-        // back
-        self.add_polygon(
-            x + width,
-            y - height,
-            z - depth,
-            x,
-            y - height,
-            z - depth,
-            x + width,
-            y,
-            z - depth,
-        );
-        self.add_polygon(
-            x,
-            y - height,
-            z - depth,
-            x,
-            y,
-            z - depth,
-            x + width,
-            y,
-            z - depth,
-        );
-        // left
-        self.add_polygon(x, y - height, z - depth, x, y, z, x, y, z - depth);
-        self.add_polygon(x, y - height, z - depth, x, y - height, z, x, y, z);
-        // top
-        self.add_polygon(x, y, z - depth, x, y, z, x + width, y, z);
-        self.add_polygon(x + width, y, z, x + width, y, z - depth, x, y, z - depth);
-        // bottom
-        self.add_polygon(
-            x,
-            y - height,
-            z,
-            x,
-            y - height,
-            z - depth,
-            x + width,
-            y - height,
-            z - depth,
-        );
-        self.add_polygon(
-            x,
-            y - height,
-            z,
-            x + width,
-            y - height,
-            z - depth,
-            x + width,
-            y - height,
-            z,
-        );
-    }
-
-    /// add_sphere()
-    /// Inputs:   struct matrix * points
-    /// double cx
-    /// double cy
-    /// double cz
-    /// double r
-    /// int step  
-
-    /// adds all the points for a sphere with center (cx, cy, cz)
-    /// and radius r using step points per circle/semicircle.
-
-    /// Since edges are drawn using 2 points, add each point twice,
-    /// or add each point and then another point 1 pixel away.
-
-    /// should call generate_sphere to create the necessary points
-    pub fn add_sphere(&mut self, cx: f32, cy: f32, cz: f32, r: f32, step: i32) {
-        let lat_start: usize = 0;
-        let lat_stop = step as usize;
-        let long_start: usize = 0;
-        let long_stop = step as usize;
-        let points_matrix = Matrix::generate_sphere(cx, cy, cz, r, step);
-        for lat in lat_start..lat_stop + 1 {
-            for longt in long_start..long_stop + 1 {
-                let index = lat * step as usize + longt;
-                self.add_polygon(
-                    points_matrix.matrix_array[0][index % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1][index % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2][index % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[0]
-                        [(index + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1]
-                        [(index + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2]
-                        [(index + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[0]
-                        [(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1]
-                        [(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2]
-                        [(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                );
-                self.add_polygon(
-                    points_matrix.matrix_array[0][index % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1][index % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2][index % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[0]
-                        [(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1]
-                        [(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2]
-                        [(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[0]
-                        [(index + step as usize) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1]
-                        [(index + step as usize) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2]
-                        [(index + step as usize) % points_matrix.matrix_array[0].len()],
-                );
-            }
-        }
-    }
-
     /// generate_sphere()
     /// Inputs:   struct matrix * points
     ///         double cx
@@ -487,8 +501,8 @@ impl Matrix {
     ///         of a sphere with center (cx, cy, cz) and
     ///         radius r using step points per circle/semicircle.
     ///         Returns a matrix of those points
-    pub fn generate_sphere(cx: f32, cy: f32, cz: f32, r: f32, step: i32) -> Matrix {
-        let mut matrix = Matrix::new(0, 0);
+    pub fn generate_sphere(cx: f32, cy: f32, cz: f32, r: f32, step: i32) -> VertexBuffer {
+        let mut matrix = VertexBuffer::new();
         let rot_start = 0;
         let rot_stop = step;
         let circ_start = 0;
@@ -507,56 +521,7 @@ impl Matrix {
                 matrix.add_point(x, y, z);
             }
         }
-        return matrix;
-    }
-
-    /// add_torus()
-    /// Inputs:   struct matrix * points
-    ///             double cx
-    ///             double cy
-    ///             double cz
-    ///             double r1
-    ///             double r2
-    ///             double step
-    /// Returns:
-    ///
-    /// adds all the points required for a torus with center (cx, cy, cz),
-    /// circle radius r1 and torus radius r2 using step points per circle.
-
-    /// should call generate_torus to create the necessary points
-    pub fn add_torus(&mut self, cx: f32, cy: f32, cz: f32, r1: f32, r2: f32, step: i32) {
-        let points_matrix = Matrix::generate_torus(cx, cy, cz, r1, r2, step);
-        let lat_start: usize = 0;
-        let lat_stop = step as usize;
-        let long_start: usize = 0;
-        let long_stop = step as usize;
-        for lat in lat_start..lat_stop + 1 {
-            for longt in long_start..long_stop + 1 {
-                let index = lat * step as usize + longt;
-                self.add_polygon(
-                    points_matrix.matrix_array[0][index],
-                    points_matrix.matrix_array[1][index],
-                    points_matrix.matrix_array[2][index],
-                    points_matrix.matrix_array[0][index + 1],
-                    points_matrix.matrix_array[1][index + 1],
-                    points_matrix.matrix_array[2][index + 1],
-                    points_matrix.matrix_array[0][(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1][(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2][(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                );
-                self.add_polygon(
-                    points_matrix.matrix_array[0][(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1][(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2][(index + step as usize + 1) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[0][index + 1],
-                    points_matrix.matrix_array[1][index + 1],
-                    points_matrix.matrix_array[2][index + 1],
-                    points_matrix.matrix_array[0][(index + step as usize + 2) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[1][(index + step as usize + 2) % points_matrix.matrix_array[0].len()],
-                    points_matrix.matrix_array[2][(index + step as usize + 2) % points_matrix.matrix_array[0].len()],
-                );
-            }
-        }
+        matrix
     }
 
     /// generate_torus()
@@ -585,12 +550,12 @@ impl Matrix {
         circle_radius: f32,
         torus_radius: f32,
         step: i32,
-    ) -> Matrix {
+    ) -> VertexBuffer {
         let rot_start = 0;
         let rot_stop = step;
         let circ_start = 0;
         let circ_stop = step;
-        let mut matrix = Matrix::new(0, 0);
+        let mut matrix = VertexBuffer::new();
         for phi in rot_start..rot_stop + 1 {
             for theta in circ_start..circ_stop + 1 {
                 let x = (f32::consts::PI * 2.0 * phi as f32 / step as f32).cos()
@@ -606,57 +571,196 @@ impl Matrix {
                 matrix.add_point(x, y, z);
             }
         }
-        return matrix;
+        matrix
     }
+}
 
-    ///======== void add_polygon() ==========
-    ///
-    ///Inputs:   struct matrix *polygons
-    ///
-    ///            x0: f32
-    ///
-    ///            y0: f32
-    ///
-    ///            z0: f32
-    ///
-    ///            x1: f32
-    ///
-    ///            y1: f32
+impl Mesh {
+    /// add_box()
+    /// Inputs:   matrix * edges
     ///
-    ///            z1: f32
+    ///             double x
     ///
-    ///            x2: f32
+    ///             double y
     ///
-    ///            y2: f32
+    ///             double z
     ///
-    ///            z2: f32
+    ///             double width
     ///
-    ///Returns:
+    ///             double height
     ///
-    ///Adds the vertices (x0, y0, z0), (x1, y1, z1)
-    ///and (x2, y2, z2) to the polygon matrix. They
-    ///define a single triangle surface.
-    ///====================
-    pub fn add_polygon(
-        &mut self,
-        x0: f32,
-        y0: f32,
-        z0: f32,
-        x1: f32,
-        y1: f32,
-        z1: f32,
-        x2: f32,
-        y2: f32,
-        z2: f32,
-    ) {
-        // check for degen triangles, if it is, then don't add
-        if (x0 as i32, y0 as i32, z0 as i32) != (x1 as i32, y1 as i32, z1 as i32)
-            && (x0 as i32, y0 as i32, z0 as i32) != (x2 as i32, y2 as i32, z2 as i32)
-            && (x1 as i32, y1 as i32, z1 as i32) != (x2 as i32, y2 as i32, z2 as i32)
-        {
-            self.add_point(x0, y0, z0);
-            self.add_point(x1, y1, z1);
-            self.add_point(x2, y2, z2);
+    ///             double depth
+
+    /// add the points and triangles for a rectagular prism whose
+    /// upper-left-front corner is (x, y, z) with width,
+    /// height and depth dimensions.
+    pub fn add_box(&mut self, x: f32, y: f32, z: f32, width: f32, height: f32, depth: f32) {
+        // front
+        self.add_quad([[x + width, y - height, z], [x + width, y, z], [x, y, z], [x, y - height, z]]);
+        // right
+        self.add_quad([[x + width, y, z], [x + width, y - height, z], [x + width, y - height, z - depth], [x + width, y, z - depth]]);
+        // back
+        self.add_quad([[x, y - height, z - depth], [x, y, z - depth], [x + width, y, z - depth], [x + width, y - height, z - depth]]);
+        // left
+        self.add_quad([[x, y - height, z - depth], [x, y - height, z], [x, y, z], [x, y, z - depth]]);
+        // top
+        self.add_quad([[x, y, z - depth], [x, y, z], [x + width, y, z], [x + width, y, z - depth]]);
+        // bottom
+        self.add_quad([[x, y - height, z], [x, y - height, z - depth], [x + width, y - height, z - depth], [x + width, y - height, z]]);
+    }
+
+    /// adds four corners going counter clockwise from the outside as two triangles. The
+    /// corners are not shared with the other faces, so shading keeps the edges sharp
+    fn add_quad(&mut self, corners: [[f32; 3]; 4]) {
+        let [a, b, c, d] = corners.map(|[x, y, z]| self.add_vertex(x, y, z));
+        self.add_polygon([a, b, c]);
+        self.add_polygon([a, c, d]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Filter;
+
+    const SIZE: usize = 16;
+
+    fn triangle(corners: [(f32, f32, f32); 3]) -> Triangle {
+        (corners, [LinearColor::new_values(1.0, 1.0, 1.0); 3])
+    }
+
+    /// which pixels the triangles fill when drawn onto an empty screen, by row and column
+    fn covered(triangles: &[Triangle]) -> Vec<Vec<bool>> {
+        let mut image = Image::new(SIZE, SIZE);
+        image.fill_triangles(triangles);
+        image.z_buffer.iter().map(|row| row.iter().map(|depth| *depth != f32::MIN).collect()).collect()
+    }
+
+    /// adds up how many of the triangles cover each pixel, filling each on its own
+    fn coverage_counts(triangles: &[Triangle]) -> Vec<Vec<usize>> {
+        let mut counts = vec![vec![0; SIZE]; SIZE];
+        for triangle in triangles {
+            for (count_row, covered_row) in counts.iter_mut().zip(covered(&[*triangle])) {
+                for (count, covered) in count_row.iter_mut().zip(covered_row) {
+                    *count += covered as usize;
+                }
+            }
         }
+        counts
+    }
+
+    /// the rows of the saved image with anything drawn in them, and whether every pixel away
+    /// from the ends of those rows is fully lit
+    fn line_rows(supersample: usize, style: LineStyle) -> Vec<(usize, bool)> {
+        let mut image = Image::new(SIZE, SIZE);
+        if supersample > 1 {
+            image.set_antialias(supersample, Filter::Box);
+        }
+        image.line_style = style;
+        image.draw_stroke(&[0.0, 10.0, 0.0, 1.0], &[15.0, 10.0, 0.0, 1.0], &LinearColor::new_values(1.0, 1.0, 1.0));
+        image
+            .output_pixels()
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.iter().any(|pixel| pixel.r > 0))
+            .map(|(i, row)| (i, row[1..SIZE - 1].iter().all(|pixel| (pixel.r, pixel.g, pixel.b) == (255, 255, 255))))
+            .collect()
+    }
+
+    #[test]
+    fn antialiased_lines_cover_the_same_row() {
+        let row = SIZE - 1 - 10;
+        assert_eq!(line_rows(1, LineStyle::Sharp), [(row, true)]);
+        for supersample in [2, 3, 4] {
+            assert_eq!(line_rows(supersample, LineStyle::Sharp), [(row, true)], "antialias {}", supersample);
+            assert_eq!(line_rows(supersample, LineStyle::Smooth), [(row, true)], "antialias {}", supersample);
+        }
+    }
+
+    #[test]
+    fn top_left_edges_go_down_or_left() {
+        assert!(is_top_left((0, 10), (0, 0)));
+        assert!(is_top_left((10, 5), (0, 5)));
+        assert!(!is_top_left((0, 0), (0, 10)));
+        assert!(!is_top_left((0, 5), (10, 5)));
+        assert_eq!(edge_function((0, 0), (10, 0), (5, 5)), 50);
+        assert_eq!(edge_function((0, 0), (10, 0), (5, -5)), -50);
+        assert_eq!(edge_function((0, 0), (10, 0), (20, 0)), 0);
+    }
+
+    #[test]
+    fn triangles_sharing_an_edge_cover_each_pixel_once() {
+        // the diagonal runs through the centre of every pixel on it
+        let (a, b, c, d) = ((0.0, 0.0, 0.0), (16.0, 0.0, 0.0), (16.0, 16.0, 0.0), (0.0, 16.0, 0.0));
+        let counts = coverage_counts(&[triangle([a, b, c]), triangle([a, c, d])]);
+        assert!(counts.iter().flatten().all(|count| *count == 1));
+    }
+
+    #[test]
+    fn fan_covers_each_pixel_once() {
+        // edges from the centre pass through pixel centres across, along and diagonally
+        let centre = (8.5, 8.5, 0.0);
+        let outside = [(-1.0, -1.0), (8.5, -1.0), (17.5, -1.0), (17.5, 8.5), (17.5, 17.5), (5.3, 17.5), (-1.0, 17.5), (-1.0, 8.5), (-1.0, 3.2)];
+        let triangles: Vec<Triangle> = outside
+            .iter()
+            .zip(outside.iter().cycle().skip(1))
+            .map(|(&(x0, y0), &(x1, y1))| triangle([centre, (x0, y0, 0.0), (x1, y1, 0.0)]))
+            .collect();
+        let counts = coverage_counts(&triangles);
+        assert!(counts.iter().flatten().all(|count| *count == 1), "{:?}", counts);
+    }
+
+    #[test]
+    fn zero_area_and_backwards_triangles_plot_nothing() {
+        let nothing = |triangles: &[Triangle]| !covered(triangles).iter().flatten().any(|covered| *covered);
+        assert!(nothing(&[triangle([(0.0, 0.0, 0.0), (8.5, 8.5, 0.0), (16.0, 16.0, 0.0)])]));
+        assert!(nothing(&[triangle([(4.5, 4.5, 0.0), (4.5, 4.5, 0.0), (4.5, 4.5, 0.0)])]));
+        assert!(nothing(&[triangle([(0.0, 0.0, 0.0), (16.0, 16.0, 0.0), (16.0, 0.0, 0.0)])]));
+        assert!(nothing(&[triangle([(0.0, 0.0, 0.0), (f32::NAN, 0.0, 0.0), (0.0, 16.0, 0.0)])]));
+    }
+
+    #[test]
+    fn depth_is_stored_at_full_precision() {
+        let mut image = Image::new(SIZE, SIZE);
+        image.fill_triangles(&[triangle([(0.0, 0.0, 0.125), (16.0, 0.0, 16.125), (0.0, 16.0, 0.125)])]);
+        for (i, row) in image.z_buffer.iter().enumerate() {
+            for (v, depth) in row.iter().enumerate() {
+                if *depth != f32::MIN {
+                    // the depth grows by 1 per pixel to the right, starting half a pixel in
+                    assert_eq!(*depth, v as f32 + 0.625, "row {} column {}", i, v);
+                }
+            }
+        }
+        let mut image = Image::new(SIZE, SIZE);
+        image.fill_triangles(&[triangle([(0.0, 0.0, -1234.567), (16.0, 0.0, -1234.567), (0.0, 16.0, -1234.567)])]);
+        assert_eq!(image.z_buffer[SIZE - 1][0], -1234.567);
+    }
+
+    #[test]
+    fn bands_fill_the_same_as_one_pass() {
+        let mut seed = 7_u32;
+        let mut random = |range: f32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32 * range
+        };
+        let triangles: Vec<Triangle> = (0..MIN_PARALLEL_TRIANGLES * 2)
+            .map(|_| {
+                let corners = [(); 3].map(|_| (random(120.0) - 10.0, random(120.0) - 10.0, random(100.0)));
+                let colors = [(); 3].map(|_| LinearColor::new_values(random(1.0), random(1.0), random(1.0)));
+                (corners, colors)
+            })
+            .collect();
+        let render = |threads: usize| {
+            let mut image = Image::new(100, 100);
+            image.threads = threads;
+            image.fill_triangles(&triangles);
+            let colors: Vec<(u8, u8, u8)> = image.screen.iter().flatten().map(|color| (color.r, color.g, color.b)).collect();
+            let depths: Vec<u32> = image.z_buffer.iter().flatten().map(|depth| depth.to_bits()).collect();
+            (colors, depths)
+        };
+        let single = render(1);
+        assert!(single.1.iter().any(|depth| *depth != f32::MIN.to_bits()));
+        assert!(single == render(4));
+        assert!(single == render(3));
     }
 }
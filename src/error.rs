@@ -0,0 +1,109 @@
+//! Errors found while parsing or running an MDL script, pointing back at the source.
+
+use crate::parser::Rule;
+use std::fmt;
+
+/// where in a script something happened
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub file: String,
+    /// 1 based
+    pub line: usize,
+    /// 1 based, counted in characters
+    pub column: usize,
+    /// the whole source line the span starts on, without its newline
+    pub snippet: String,
+    /// how many characters of the snippet to underline
+    pub length: usize,
+}
+
+impl Span {
+    pub fn from_pest(file: &str, span: &pest::Span) -> Span {
+        let (line, column) = span.start_pos().line_col();
+        let snippet = span.start_pos().line_of().trim_end_matches(&['\r', '\n'][..]).to_owned();
+        let rest_of_line = snippet.chars().count().saturating_sub(column - 1);
+        let length = span.as_str().lines().next().map_or(1, |first| first.chars().count()).clamp(1, rest_of_line.max(1));
+        Span {
+            file: file.to_owned(),
+            line,
+            column,
+            snippet,
+            length,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MdlError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl MdlError {
+    /// an error that is not tied to any place in a script, like a missing file
+    pub fn new(message: impl Into<String>) -> MdlError {
+        MdlError {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    pub fn with_span(message: impl Into<String>, span: &Span) -> MdlError {
+        MdlError {
+            message: message.into(),
+            span: Some(span.clone()),
+        }
+    }
+
+    /// turns a grammar error from pest into one pointing at the offending spot
+    pub fn from_pest(file: &str, source: &str, error: pest::error::Error<Rule>) -> MdlError {
+        let (line, column) = match error.line_col {
+            pest::error::LineColLocation::Pos(position) => position,
+            pest::error::LineColLocation::Span(start, _) => start,
+        };
+        let snippet = source.lines().nth(line - 1).unwrap_or("").trim_end_matches('\r').to_owned();
+        let message = match &error.variant {
+            pest::error::ErrorVariant::ParsingError { .. } => {
+                let word: String = snippet.chars().skip(column - 1).take_while(|c| !c.is_whitespace()).collect();
+                if word.is_empty() {
+                    String::from("unexpected end of line")
+                } else {
+                    format!("unexpected `{}`, this is not a command or does not fit its arguments", word)
+                }
+            }
+            pest::error::ErrorVariant::CustomError { message } => message.clone(),
+        };
+        MdlError {
+            message,
+            span: Some(Span {
+                file: file.to_owned(),
+                line,
+                column,
+                snippet,
+                length: 1,
+            }),
+        }
+    }
+}
+
+impl From<String> for MdlError {
+    fn from(message: String) -> MdlError {
+        MdlError::new(message)
+    }
+}
+
+impl fmt::Display for MdlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(span) = &self.span {
+            let gutter = " ".repeat(span.line.to_string().len());
+            write!(f, "\n{}--> {}:{}:{}", gutter, span.file, span.line, span.column)?;
+            write!(f, "\n{} |", gutter)?;
+            write!(f, "\n{} | {}", span.line, span.snippet)?;
+            // tabs are kept so the caret lines up with the snippet
+            let padding: String = span.snippet.chars().take(span.column - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+            write!(f, "\n{} | {}{}", gutter, padding, "^".repeat(span.length))?;
+        }
+        Ok(())
+    }
+}
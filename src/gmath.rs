@@ -1,69 +1,60 @@
 use crate::Color;
-use crate::Matrix;
+use crate::color::LinearColor;
+use crate::mesh::Mesh;
 use crate::ReflectionValue;
-use std::cmp;
-use std::ops::Add;
+use std::ops::{Add, Mul, Sub};
 
 //vector functions
 //normalize vector, should modify the parameter
-pub fn normalize(vector: &mut Vec<f32>) {
+pub fn normalize(vector: &mut [f32; 3]) {
   let mut m = 0.0;
-  for i in 0..vector.len() {
-    m += vector[i].powi(2);
+  for value in vector.iter() {
+    m += value.powi(2);
   }
   m = m.sqrt();
-  for i in 0..vector.len() {
-    vector[i] /= m;
+  for value in vector.iter_mut() {
+    *value /= m;
   }
 }
 
 // Return the dot product of a . b
-pub fn dot_product(lhs: &Vec<f32>, rhs: &Vec<f32>) -> f32 {
+pub fn dot_product(lhs: &[f32; 3], rhs: &[f32; 3]) -> f32 {
   let mut result = 0.0;
-  for i in 0..cmp::min(lhs.len(), rhs.len()) {
-    result += lhs[i] * rhs[i];
+  for (l, r) in lhs.iter().zip(rhs) {
+    result += l * r;
   }
-  return result;
+  result
 }
 
-// modify the param vector when the vector is multiplied by a scalar
-pub fn vector_times_scalar(vector: &mut Vec<f32>, scalar: f32) -> Vec<f32> {
-  let mut result = vector.clone();
-  for i in 0..result.len() {
-    result[i] *= scalar;
-  }
-  return result;
+// the vector multiplied by a scalar
+pub fn vector_times_scalar(vector: &[f32; 3], scalar: f32) -> [f32; 3] {
+  vector.map(|value| value * scalar)
+}
+
+pub fn vector_subtraction(lhs: &[f32; 3], rhs: &[f32; 3]) -> [f32; 3] {
+  [lhs[0] - rhs[0], lhs[1] - rhs[1], lhs[2] - rhs[2]]
 }
 
-pub fn vector_subtraction(lhs: &mut Vec<f32>, rhs: &mut Vec<f32>) -> Vec<f32> {
-  let mut result = lhs.clone();
-  for i in 0..result.len() {
-    result[i] -= rhs[i];
+impl Mesh {
+  //Calculate the surface normal for the triangle
+  //at index i in the mesh
+  pub fn calculate_normal(&self, i: usize) -> [f32; 3] {
+    self.normal_of(self.triangles[i])
   }
-  return result;
-}
-
-impl Matrix {
-  //Calculate the surface normal for the triangle whose first
-  //point is located at index i in polygons
-  pub fn calculate_normal(&self, i: usize) -> Vec<f32> {
-    let x0 = self.matrix_array[0][i];
-    let y0 = self.matrix_array[1][i];
-    let z0 = self.matrix_array[2][i];
-    let x1 = self.matrix_array[0][i + 1];
-    let y1 = self.matrix_array[1][i + 1];
-    let z1 = self.matrix_array[2][i + 1];
-    let x2 = self.matrix_array[0][i + 2];
-    let y2 = self.matrix_array[1][i + 2];
-    let z2 = self.matrix_array[2][i + 2];
+
+  //the surface normal of the triangle with these corners,
+  //which is zero when it has no area
+  pub fn normal_of(&self, corners: [u32; 3]) -> [f32; 3] {
+    let [x0, y0, z0, _] = self.vertices[corners[0] as usize];
+    let [x1, y1, z1, _] = self.vertices[corners[1] as usize];
+    let [x2, y2, z2, _] = self.vertices[corners[2] as usize];
     let ax = x1 - x0;
     let ay = y1 - y0;
     let az = z1 - z0;
     let bx = x2 - x0;
     let by = y2 - y0;
     let bz = z2 - z0;
-    return vec![ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx];
-    // return dot_product(&n, &consts::view);
+    [ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx]
   }
 }
 
@@ -73,9 +64,9 @@ impl Color {
     let g: u8 = if constant * light_color.g as f32 * reflect.g > 255.0 {255} else{(constant * light_color.g as f32 * reflect.g) as u8};
     let b: u8 = if constant * light_color.b as f32 * reflect.b > 255.0 {255} else{(constant * light_color.b as f32 * reflect.b) as u8};
     Color {
-      r: r,
-      g: g,
-      b: b,
+      r,
+      g,
+      b,
     }
   }
 }
@@ -88,9 +79,56 @@ impl Add for Color {
     let g: u8 = if self.g as i32 + other.g as i32 > 255 {255} else{self.g + other.g};
     let b: u8 = if self.b as i32 + other.b as i32 > 255 {255} else{self.b + other.b};
     Self {
-      r: r,
-      g: g,
-      b: b,
+      r,
+      g,
+      b,
+    }
+  }
+}
+
+impl LinearColor {
+  fn linear_with_lighting(constant: f32, light_color: &LinearColor, reflect: &ReflectionValue) -> LinearColor {
+    let constant = constant.max(0.0);
+    LinearColor {
+      r: constant * light_color.r * reflect.r,
+      g: constant * light_color.g * reflect.g,
+      b: constant * light_color.b * reflect.b,
+    }
+  }
+}
+
+impl Add for LinearColor {
+  type Output = Self;
+
+  fn add(self, other: Self) -> Self {
+    Self {
+      r: self.r + other.r,
+      g: self.g + other.g,
+      b: self.b + other.b,
+    }
+  }
+}
+
+impl Sub for LinearColor {
+  type Output = Self;
+
+  fn sub(self, other: Self) -> Self {
+    Self {
+      r: self.r - other.r,
+      g: self.g - other.g,
+      b: self.b - other.b,
+    }
+  }
+}
+
+impl Mul<f32> for LinearColor {
+  type Output = Self;
+
+  fn mul(self, factor: f32) -> Self {
+    Self {
+      r: self.r * factor,
+      g: self.g * factor,
+      b: self.b * factor,
     }
   }
 }
@@ -111,11 +149,11 @@ doubles (red, green, blue)
 //lighting functions
 
 pub fn get_lighting(
-  normal: &mut Vec<f32>,
-  view: &mut Vec<f32>,
+  normal: &mut [f32; 3],
+  view: &mut [f32; 3],
   ambient_light: &Color,
   point_light_color: &Color,
-  point_light_vector: &mut Vec<f32>,
+  point_light_vector: &mut [f32; 3],
   ambient_reflect: &ReflectionValue,
   diffuse_reflect: &ReflectionValue,
   specular_reflect: &ReflectionValue,
@@ -136,42 +174,78 @@ pub fn get_lighting(
     view,
     normal,
   );
-  return ambient_color + diffuse_color + specular_color;
+  ambient_color + diffuse_color + specular_color
+}
+
+// the lights of a scene and the reflection constants of what they shine on, with
+// the light colors decoded from sRGB since lighting adds up linear light
+pub struct LinearLighting<'a> {
+  // normalized, like point_light_vector
+  pub view: [f32; 3],
+  pub ambient_light: LinearColor,
+  pub point_light_color: LinearColor,
+  pub point_light_vector: [f32; 3],
+  pub ambient_reflect: &'a ReflectionValue,
+  pub diffuse_reflect: &'a ReflectionValue,
+  pub specular_reflect: &'a ReflectionValue,
+}
+
+// same as get_lighting, but nothing is clamped at the top so overlapping and
+// bright lights keep their energy for tone mapping
+pub fn get_lighting_linear(normal: &[f32; 3], lighting: &LinearLighting) -> LinearColor {
+  let mut normal = *normal;
+  normalize(&mut normal);
+  let diffuse = dot_product(&normal, &lighting.point_light_vector);
+  let specular = specular_factor(&lighting.point_light_vector, &lighting.view, &normal);
+  LinearColor::linear_with_lighting(1.0, &lighting.ambient_light, lighting.ambient_reflect)
+    + LinearColor::linear_with_lighting(diffuse, &lighting.point_light_color, lighting.diffuse_reflect)
+    + LinearColor::linear_with_lighting(specular, &lighting.point_light_color, lighting.specular_reflect)
 }
 
 pub fn calculate_ambient(ambient_light: &Color, ambient_reflect: &ReflectionValue) -> Color {
-  return Color::color_with_lighting(1.0, ambient_light, ambient_reflect);
+  Color::color_with_lighting(1.0, ambient_light, ambient_reflect)
 }
 
 pub fn calculate_diffuse(
-  normalized_diffuse_light_vector: &mut Vec<f32>,
+  normalized_diffuse_light_vector: &mut [f32; 3],
   diffuse_light_color: &Color,
   diffuse_reflect: &ReflectionValue,
-  normalized_normal: &mut Vec<f32>,
+  normalized_normal: &mut [f32; 3],
 ) -> Color {
   let n_l_dot_product_times = dot_product(normalized_normal, normalized_diffuse_light_vector);
-  return Color::color_with_lighting(n_l_dot_product_times, diffuse_light_color, diffuse_reflect);
+  Color::color_with_lighting(n_l_dot_product_times, diffuse_light_color, diffuse_reflect)
 }
 
 pub fn calculate_specular(
-  normalized_specular_light_vector: &mut Vec<f32>,
+  normalized_specular_light_vector: &mut [f32; 3],
   specular_light_color: &Color,
   specular_reflect: &ReflectionValue,
-  view: &mut Vec<f32>,
-  normalized_normal: &mut Vec<f32>,
+  view: &mut [f32; 3],
+  normalized_normal: &mut [f32; 3],
 ) -> Color {
-  let calculation_before_color_and_light = &mut vector_subtraction(
-    &mut vector_times_scalar(
-      normalized_normal,
-      2.0 * dot_product(normalized_normal, normalized_specular_light_vector),
-    ),
-    normalized_specular_light_vector,
-  );
   normalize(view);
-  let calculation_before_color = dot_product(calculation_before_color_and_light, view);
-  return Color::color_with_lighting(
+  let calculation_before_color = specular_factor(normalized_specular_light_vector, view, normalized_normal);
+  Color::color_with_lighting(
     calculation_before_color,
     specular_light_color,
     specular_reflect,
+  )
+}
+
+
+// the reflection of the light vector off the surface, dotted with the normalized
+// view vector
+fn specular_factor(
+  normalized_specular_light_vector: &[f32; 3],
+  normalized_view: &[f32; 3],
+  normalized_normal: &[f32; 3],
+) -> f32 {
+  let calculation_before_color_and_light = &vector_subtraction(
+    &vector_times_scalar(
+      normalized_normal,
+      2.0 * dot_product(normalized_normal, normalized_specular_light_vector),
+    ),
+    normalized_specular_light_vector,
   );
+  dot_product(calculation_before_color_and_light, normalized_view)
 }
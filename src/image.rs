@@ -1,16 +1,119 @@
-use crate::color::Color;
+use crate::color::{linear_to_srgb, Color, LinearColor};
+use crate::png;
+use crate::terminal;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use std::env;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
 use std::path::Path;
 use std::process::Command;
 
+#[derive(Clone)]
+pub enum Background {
+    Solid(Color),
+    /// top color, then bottom color
+    Gradient(Color, Color),
+    /// pixels of the background picture, scaled to the canvas when cleared
+    Picture(Vec<Vec<Color>>),
+}
+
+/// how the supersampled screen is reduced to the output resolution
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Filter {
+    /// plain average of the samples inside each output pixel
+    Box,
+    /// samples weighted by distance from the output pixel center, reaching into neighboring pixels
+    Tent,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineStyle {
+    /// single pixel Bresenham lines, or hard edged spans for wide lines
+    Sharp,
+    /// coverage blended lines after Xiaolin Wu
+    Smooth,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Shading {
+    Flat,
+    /// each corner is lit with the normal of the surface around it and the colors are
+    /// blended across the triangle
+    Gouraud,
+    /// only the edges of each triangle are drawn, using the line style
+    Wireframe,
+}
+
+/// curve used to squeeze unbounded linear light into the displayable range
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ToneMap {
+    Reinhard,
+    /// the Narkowicz fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(&self, value: f32) -> f32 {
+        let value = value.max(0.0);
+        match self {
+            ToneMap::Reinhard => value / (1.0 + value),
+            ToneMap::Aces => {
+                ((value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    /// the value that apply maps to mapped, for mapped from 0 up to just below 1
+    pub fn invert(&self, mapped: f32) -> f32 {
+        let mapped = mapped.clamp(0.0, 0.999);
+        match self {
+            ToneMap::Reinhard => mapped / (1.0 - mapped),
+            ToneMap::Aces => {
+                // positive root of (2.43y - 2.51)x^2 + (0.59y - 0.03)x + 0.14y = 0
+                let a = 2.43 * mapped - 2.51;
+                let b = 0.59 * mapped - 0.03;
+                let c = 0.14 * mapped;
+                (-b - (b * b - 4.0 * a * c).sqrt()) / (2.0 * a)
+            }
+        }
+    }
+}
+
+/// where display shows the picture
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Preview {
+    /// a window when there is a program to open one, otherwise the terminal
+    Auto,
+    Window,
+    /// truecolor half block characters
+    Terminal,
+    Sixel,
+}
+
+/// screen and z_buffer are stored at supersample times the output resolution,
+/// so height and width are the size of the internal buffers
+#[derive(Clone)]
 pub struct Image {
     pub screen: Vec<Vec<Color>>,
     pub z_buffer: Vec<Vec<f32>>,
     pub height: usize,
     pub width: usize,
+    pub background: Background,
+    pub supersample: usize,
+    pub filter: Filter,
+    pub line_style: LineStyle,
+    /// in output pixels
+    pub line_width: f32,
+    pub shading: Shading,
+    /// linear light framebuffer drawn into instead of screen when hdr is on
+    pub hdr_screen: Option<Vec<Vec<LinearColor>>>,
+    /// in stops, so every 1.0 doubles the light before tone mapping
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    /// how many threads fill in triangles
+    pub threads: usize,
 }
 
 impl Image {
@@ -20,29 +123,224 @@ impl Image {
             z_buffer: vec![vec![f32::MIN; image_width]; image_height],
             width: image_width,
             height: image_height,
+            background: Background::Solid(Color::new()),
+            supersample: 1,
+            filter: Filter::Box,
+            line_style: LineStyle::Sharp,
+            line_width: 1.0,
+            shading: Shading::Flat,
+            hdr_screen: None,
+            exposure: 0.0,
+            tone_map: ToneMap::Reinhard,
+            threads: 1,
+        }
+    }
+
+    /// renders at factor times the output resolution from now on, reallocating and clearing the canvas
+    pub fn set_antialias(&mut self, factor: usize, filter: Filter) {
+        let output_width = self.width / self.supersample;
+        let output_height = self.height / self.supersample;
+        self.supersample = factor;
+        self.filter = filter;
+        self.width = output_width * factor;
+        self.height = output_height * factor;
+        self.screen = vec![vec![Color::new(); self.width]; self.height];
+        self.z_buffer = vec![vec![f32::MIN; self.width]; self.height];
+        if self.hdr_screen.is_some() {
+            self.hdr_screen = Some(vec![vec![LinearColor::new_values(0.0, 0.0, 0.0); self.width]; self.height]);
         }
+        self.clear();
+    }
+
+    /// draws into a floating point linear light framebuffer from now on, which is tone mapped
+    /// and gamma encoded when saved
+    pub fn set_hdr(&mut self, tone_map: ToneMap, exposure: f32) {
+        self.tone_map = tone_map;
+        self.exposure = exposure;
+        self.hdr_screen = Some(vec![vec![LinearColor::new_values(0.0, 0.0, 0.0); self.width]; self.height]);
+        self.clear();
+    }
+
+    /// the linear color to draw with for a color given in the script
+    pub fn paint(&self, color: &Color) -> LinearColor {
+        if self.hdr_screen.is_some() {
+            LinearColor::from_srgb(color)
+        } else {
+            LinearColor::from_color(color)
+        }
+    }
+
+    /// sets the background used by clear and repaints the canvas with it
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+        self.clear();
     }
 
-    pub fn plot(&mut self, x: i32, y: i32, mut z: f32, color: &Color) -> bool{
-        z = (z as i32 * 10000) as f32 / 10000.0;
+    /// the background color of the pixel in row i, column v of the screen
+    fn background_color(&self, i: usize, v: usize) -> Color {
+        match &self.background {
+            Background::Solid(color) => *color,
+            Background::Gradient(top, bottom) => {
+                let t = if self.height > 1 { i as f32 / (self.height - 1) as f32 } else { 0.0 };
+                Color::new_color(
+                    (top.r as f32 + (bottom.r as f32 - top.r as f32) * t).round() as u8,
+                    (top.g as f32 + (bottom.g as f32 - top.g as f32) * t).round() as u8,
+                    (top.b as f32 + (bottom.b as f32 - top.b as f32) * t).round() as u8,
+                )
+            }
+            Background::Picture(pixels) => {
+                let row = &pixels[i * pixels.len() / self.height];
+                row[v * row.len() / self.width]
+            }
+        }
+    }
+
+    /// converts a coordinate from output pixels to screen samples
+    pub fn to_sample(&self, coordinate: f32) -> f32 {
+        coordinate * self.supersample as f32
+    }
+
+    /// converts a line end from output pixels to screen samples. Lines take a whole number to
+    /// be the centre of that pixel, which is the middle of its block of samples
+    pub fn to_line_sample(&self, coordinate: f32) -> f32 {
+        self.to_sample(coordinate + 0.5) - 0.5
+    }
+
+    pub fn plot(&mut self, x: i32, y: i32, z: f32, color: &LinearColor) -> bool{
         if x >= 0 && y >= 0 && x < self.width as i32 && y < self.height as i32{
             if z >= self.z_buffer[(self.height - 1) - y as usize][x as usize] {
-                self.screen[(self.height - 1) - y as usize][x as usize].plot_color(color);
+                match &mut self.hdr_screen {
+                    Some(hdr_screen) => hdr_screen[(self.height - 1) - y as usize][x as usize] = *color,
+                    None => self.screen[(self.height - 1) - y as usize][x as usize] = color.to_color(),
+                }
                 self.z_buffer[(self.height - 1) - y as usize][x as usize] = z;
             }
-            return true;
+            true
         }else{
-            return false;
+            false
+        }
+    }
+
+    /// the screen reduced to the output resolution with the antialiasing filter,
+    /// tone mapped first if hdr is on
+    pub fn output_pixels(&self) -> Vec<Vec<Color>> {
+        match &self.hdr_screen {
+            None => {
+                if self.supersample == 1 {
+                    return self.screen.clone();
+                }
+                self.resolve(
+                    |i, v| [self.screen[i][v].r as f32, self.screen[i][v].g as f32, self.screen[i][v].b as f32],
+                    |[r, g, b]| Color::new_color(r.round() as u8, g.round() as u8, b.round() as u8),
+                )
+            }
+            Some(hdr_screen) => {
+                let scale = 2.0_f32.powf(self.exposure);
+                self.resolve(
+                    |i, v| [hdr_screen[i][v].r, hdr_screen[i][v].g, hdr_screen[i][v].b],
+                    |[r, g, b]| Color::new_color(
+                        linear_to_srgb(self.tone_map.apply(r * scale)),
+                        linear_to_srgb(self.tone_map.apply(g * scale)),
+                        linear_to_srgb(self.tone_map.apply(b * scale)),
+                    ),
+                )
+            }
+        }
+    }
+
+    /// filters the samples given by fetch down to the output resolution and turns each
+    /// result into a color with finish
+    fn resolve(&self, fetch: impl Fn(usize, usize) -> [f32; 3], finish: impl Fn([f32; 3]) -> Color) -> Vec<Vec<Color>> {
+        let output_width = self.width / self.supersample;
+        let output_height = self.height / self.supersample;
+        (0..output_height)
+            .map(|i| {
+                (0..output_width)
+                    .map(|v| {
+                        finish(match self.filter {
+                            Filter::Box => self.box_sample(i, v, &fetch),
+                            Filter::Tent => self.tent_sample(i, v, &fetch),
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn box_sample(&self, i: usize, v: usize, fetch: &impl Fn(usize, usize) -> [f32; 3]) -> [f32; 3] {
+        let factor = self.supersample;
+        let mut total = [0.0; 3];
+        for sample_i in i * factor..(i + 1) * factor {
+            for sample_v in v * factor..(v + 1) * factor {
+                let sample = fetch(sample_i, sample_v);
+                for channel in 0..3 {
+                    total[channel] += sample[channel];
+                }
+            }
+        }
+        let count = (factor * factor) as f32;
+        [total[0] / count, total[1] / count, total[2] / count]
+    }
+
+    fn tent_sample(&self, i: usize, v: usize, fetch: &impl Fn(usize, usize) -> [f32; 3]) -> [f32; 3] {
+        let factor = self.supersample as f32;
+        // center of the output pixel in sample coordinates
+        let center_i = (i as f32 + 0.5) * factor;
+        let center_v = (v as f32 + 0.5) * factor;
+        let first_i = (center_i - factor).floor().max(0.0) as usize;
+        let last_i = ((center_i + factor).ceil() as usize).min(self.height);
+        let first_v = (center_v - factor).floor().max(0.0) as usize;
+        let last_v = ((center_v + factor).ceil() as usize).min(self.width);
+        let mut total = [0.0; 3];
+        let mut total_weight = 0.0;
+        for sample_i in first_i..last_i {
+            let weight_i = 1.0 - ((sample_i as f32 + 0.5 - center_i).abs() / factor);
+            if weight_i <= 0.0 {
+                continue;
+            }
+            for sample_v in first_v..last_v {
+                let weight_v = 1.0 - ((sample_v as f32 + 0.5 - center_v).abs() / factor);
+                if weight_v <= 0.0 {
+                    continue;
+                }
+                let weight = weight_i * weight_v;
+                let sample = fetch(sample_i, sample_v);
+                for channel in 0..3 {
+                    total[channel] += sample[channel] * weight;
+                }
+                total_weight += weight;
+            }
+        }
+        [total[0] / total_weight, total[1] / total_weight, total[2] / total_weight]
+    }
+
+    /// mixes color into the pixel by coverage (0 to 1) if it passes the z test,
+    /// only claiming the z_buffer when the pixel is mostly covered
+    pub fn blend(&mut self, x: i32, y: i32, z: f32, color: &LinearColor, coverage: f32) {
+        if coverage <= 0.0 || x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let i = (self.height - 1) - y as usize;
+        let v = x as usize;
+        if z >= self.z_buffer[i][v] {
+            match &mut self.hdr_screen {
+                Some(hdr_screen) => hdr_screen[i][v] = hdr_screen[i][v].mix(color, coverage),
+                None => self.screen[i][v] = LinearColor::from_color(&self.screen[i][v]).mix(color, coverage).to_color(),
+            }
+            if coverage >= 0.5 {
+                self.z_buffer[i][v] = z;
+            }
         }
     }
 
     fn create_data(&self) -> String {
+        let pixels = self.output_pixels();
         let mut result: String =
-            format!("P3\n{} {}\n255\n", self.screen[0].len(), self.screen.len());
+            format!("P3\n{} {}\n255\n", pixels[0].len(), pixels.len());
 
-        for i in 0..self.screen.len() {
-            for v in 0..self.screen[i].len() {
-                result.push_str(&self.screen[i][v].to_string().to_owned());
+        for row in &pixels {
+            for pixel in row {
+                result.push_str(&pixel.to_string().to_owned());
                 result.push_str("  ");
             }
             result.push_str("\n");
@@ -50,53 +348,317 @@ impl Image {
         return result;
     }
 
-    pub fn create_file(&self, file_name: &str) {
+    pub fn create_file(&self, file_name: &str) -> Result<(), String> {
         let path = Path::new(&file_name);
 
         let mut file = match File::create(&path) {
-            Err(error) => panic!("failed to create image file because {}", error),
+            Err(error) => return Err(format!("failed to create image file {} because {}", file_name, error)),
             Ok(file) => file,
         };
 
-        let result = self.create_data();
+        let result = if file_name.to_lowercase().ends_with(".png") {
+            png::encode(&self.output_pixels())
+        } else {
+            self.create_data().into_bytes()
+        };
+
+        match file.write_all(&result) {
+            Err(error) => Err(format!("failed to write image file {} because {}", file_name, error)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// the z_buffer at the output resolution, keeping the nearest sample of each pixel,
+    /// with f32::MIN wherever nothing was drawn
+    pub fn output_depth(&self) -> Vec<Vec<f32>> {
+        let factor = self.supersample;
+        let mut depth = vec![vec![f32::MIN; self.width / factor]; self.height / factor];
+        for i in 0..self.height / factor * factor {
+            for v in 0..self.width / factor * factor {
+                let z = self.z_buffer[i][v];
+                if z > depth[i / factor][v / factor] {
+                    depth[i / factor][v / factor] = z;
+                }
+            }
+        }
+        depth
+    }
+
+    /// saves the z_buffer as a depth image.
+    ///
+    /// A .pfm file gets the raw z values as little endian floats. Anything else is written as
+    /// a grayscale ppm with the range of drawn z values stretched so the nearest is white and
+    /// the farthest is dark gray, leaving the empty background black.
+    pub fn create_depth_file(&self, file_name: &str) -> Result<(), String> {
+        let depth = self.output_depth();
+        let height = depth.len();
+        let width = if height > 0 { depth[0].len() } else { 0 };
+        let mut result: Vec<u8>;
+        if file_name.to_lowercase().ends_with(".pfm") {
+            result = format!("Pf\n{} {}\n-1.0\n", width, height).into_bytes();
+            // pfm rows go from the bottom of the image to the top
+            for row in depth.iter().rev() {
+                for z in row {
+                    result.extend_from_slice(&z.to_le_bytes());
+                }
+            }
+        } else {
+            let drawn = depth.iter().flatten().filter(|z| z.is_finite() && **z != f32::MIN);
+            let (near, far) = drawn.fold((f32::MIN, f32::MAX), |(near, far), z| (near.max(*z), far.min(*z)));
+            let mut text = format!("P3\n{} {}\n255\n", width, height);
+            for row in &depth {
+                for z in row {
+                    let gray = if !z.is_finite() || *z == f32::MIN {
+                        0
+                    } else if near == far {
+                        255
+                    } else {
+                        (32.0 + 223.0 * (z - far) / (near - far)).round() as u8
+                    };
+                    text.push_str(&format!("{} {} {}  ", gray, gray, gray));
+                }
+                text.push('\n');
+            }
+            result = text.into_bytes();
+        }
 
-        match file.write_all(result.as_bytes()) {
-            Err(error) => panic!("failed to write image file because {}", error),
-            Ok(_) => {}
+        let mut file = match File::create(Path::new(file_name)) {
+            Err(error) => return Err(format!("failed to create depth file {} because {}", file_name, error)),
+            Ok(file) => file,
         };
+        file.write_all(&result).map_err(|error| format!("failed to write depth file {} because {}", file_name, error))
     }
 
     pub fn clear(&mut self) {
         for i in 0..self.screen.len() {
             for v in 0..self.screen[0].len() {
-                self.screen[i][v].reset_color();
+                self.screen[i][v] = self.background_color(i, v);
                 self.z_buffer[i][v] = f32::MIN;
             }
         }
+        if let Some(mut hdr_screen) = self.hdr_screen.take() {
+            // store the background as the light that tone maps back to the exact background color
+            let scale = 2.0_f32.powf(self.exposure);
+            let unmap = |channel: f32| self.tone_map.invert(channel) / scale;
+            for i in 0..hdr_screen.len() {
+                for v in 0..hdr_screen[0].len() {
+                    let color = LinearColor::from_srgb(&self.screen[i][v]);
+                    hdr_screen[i][v] = LinearColor::new_values(unmap(color.r), unmap(color.g), unmap(color.b));
+                }
+            }
+            self.hdr_screen = Some(hdr_screen);
+        }
     }
 
-    pub fn display(&mut self) {
-        let mut file_name: String = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(30)
-            .map(char::from)
-            .collect();
-        file_name = format!("/tmp/imageDisplay{}.ppm", file_name);
-        self.create_file(&file_name);
-        Command::new("open")
-            .arg(file_name)
-            .spawn()
-            .expect("failed to open image");
+    pub fn display(&self, preview: Preview) -> Result<(), String> {
+        let opener = window_opener();
+        match preview {
+            Preview::Auto if opener.is_none() => self.display(Preview::Terminal),
+            Preview::Auto | Preview::Window => {
+                let opener = opener.ok_or("there is no program to open a window with, so please use --preview terminal")?;
+                let mut file_name: String = thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(30)
+                    .map(char::from)
+                    .collect();
+                file_name = format!("/tmp/imageDisplay{}.ppm", file_name);
+                self.create_file(&file_name)?;
+                match Command::new(opener).arg(file_name).spawn() {
+                    Ok(_) => Ok(()),
+                    Err(error) => Err(format!("failed to open image because {}", error)),
+                }
+            }
+            Preview::Terminal => {
+                let (columns, rows) = terminal::size();
+                write_stdout(&terminal::half_blocks(&self.output_pixels(), columns, rows))
+            }
+            Preview::Sixel => write_stdout(&terminal::sixel(&self.output_pixels())),
+        }
     }
 }
 
-pub fn make_animation(name: String){
-    println!("Rendering gif...");
-    Command::new("convert")
+/// the program that opens pictures in a window, if there is one and a screen to show it on
+fn window_opener() -> Option<&'static str> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if env::var_os("DISPLAY").is_some() || env::var_os("WAYLAND_DISPLAY").is_some() {
+        "xdg-open"
+    } else {
+        return None;
+    };
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths).any(|path| path.join(opener).is_file()).then_some(opener)
+}
+
+fn write_stdout(text: &str) -> Result<(), String> {
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(text.as_bytes())
+        .and_then(|_| stdout.flush())
+        .map_err(|error| format!("failed to show the image in the terminal because {}", error))
+}
+
+/// reads a P3 or P6 ppm file into rows of pixels, top row first
+pub fn read_ppm(file_name: &str) -> Result<Vec<Vec<Color>>, String> {
+    let mut data = Vec::new();
+    match File::open(file_name) {
+        Err(error) => return Err(format!("failed to open image file {} because {}", file_name, error)),
+        Ok(mut file) => {
+            if let Err(error) = file.read_to_end(&mut data) {
+                return Err(format!("failed to read image file {} because {}", file_name, error));
+            }
+        }
+    };
+    decode_ppm(&data, file_name)
+}
+
+/// reads the contents of a ppm file, with file_name only used in errors
+fn decode_ppm(data: &[u8], file_name: &str) -> Result<Vec<Vec<Color>>, String> {
+    // the header is magic number, width, height and max value, with # comments allowed between them
+    let mut header: Vec<String> = Vec::new();
+    let mut index = 0;
+    while header.len() < 4 && index < data.len() {
+        if data[index] == b'#' {
+            while index < data.len() && data[index] != b'\n' {
+                index += 1;
+            }
+        } else if data[index].is_ascii_whitespace() {
+            index += 1;
+        } else {
+            let start = index;
+            while index < data.len() && !data[index].is_ascii_whitespace() {
+                index += 1;
+            }
+            header.push(String::from_utf8_lossy(&data[start..index]).to_string());
+        }
+    }
+    if header.len() < 4 || (header[0] != "P3" && header[0] != "P6") {
+        return Err(format!("{} is not a P3 or P6 ppm file", file_name));
+    }
+    let width: usize = header[1].parse().map_err(|_| format!("invalid width in {}", file_name))?;
+    let height: usize = header[2].parse().map_err(|_| format!("invalid height in {}", file_name))?;
+    let max_value: u32 = header[3].parse().map_err(|_| format!("invalid max value in {}", file_name))?;
+    if width == 0 || height == 0 || max_value == 0 || max_value > 255 {
+        return Err(format!("unsupported dimensions or max value in {}", file_name));
+    }
+    let scale = |value: u32| (value * 255 / max_value) as u8;
+
+    let samples: Vec<u32> = if header[0] == "P6" {
+        // exactly one whitespace byte separates the header from the binary pixels
+        data.iter().skip(index + 1).map(|byte| *byte as u32).collect()
+    } else {
+        let mut samples = Vec::new();
+        for word in String::from_utf8_lossy(&data[index..]).split_ascii_whitespace() {
+            samples.push(word.parse().map_err(|_| format!("invalid pixel value {} in {}", word, file_name))?);
+        }
+        samples
+    };
+    if samples.len() / 3 / width < height {
+        return Err(format!("{} has fewer pixels than its header says", file_name));
+    }
+    let samples = &samples[..width * height * 3];
+    if let Some(sample) = samples.iter().find(|sample| **sample > max_value) {
+        return Err(format!("pixel value {} in {} is over the max value", sample, file_name));
+    }
+
+    Ok(samples
+        .chunks_exact(3)
+        .map(|pixel| Color::new_color(scale(pixel[0]), scale(pixel[1]), scale(pixel[2])))
+        .collect::<Vec<Color>>()
+        .chunks(width)
+        .map(|row| row.to_vec())
+        .collect())
+}
+
+/// joins the numbered frames of basename in output_dir into output_dir/basename.gif
+pub fn make_animation(output_dir: &str, basename: &str, extension: &str) -> Result<(), String> {
+    info!("Rendering gif...");
+    let status = Command::new("convert")
         .arg("-delay")
         .arg("1.7")
-        .arg("animation/".to_owned()+&name+"*")
-        .arg(name+".gif")
-        .spawn()
-        .expect("ERROR: unable to convert a series of images to a gif");
-}
\ No newline at end of file
+        .arg(format!("{}/{}*.{}", output_dir, basename, extension))
+        .arg(format!("{}/{}.gif", output_dir, basename))
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("convert failed to make a gif ({})", status)),
+        Err(error) => Err(format!("unable to run convert to make a gif because {}", error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channels(pixels: &[Vec<Color>]) -> Vec<Vec<(u8, u8, u8)>> {
+        pixels.iter().map(|row| row.iter().map(|pixel| (pixel.r, pixel.g, pixel.b)).collect()).collect()
+    }
+
+    #[test]
+    fn reads_p3_with_comments() {
+        let data = b"P3\n# made by hand\n2 2 # width and height\n10\n10 0 0  0 10 0\n0 0 10  5 5 5\n";
+        let pixels = decode_ppm(data, "test.ppm").unwrap();
+        assert_eq!(channels(&pixels), [[(255, 0, 0), (0, 255, 0)], [(0, 0, 255), (127, 127, 127)]]);
+    }
+
+    #[test]
+    fn reads_p6() {
+        let mut data = b"P6\n# binary\n2 1\n255\n".to_vec();
+        data.extend_from_slice(&[1, 2, 3, 250, 251, 252]);
+        let pixels = decode_ppm(&data, "test.ppm").unwrap();
+        assert_eq!(channels(&pixels), [[(1, 2, 3), (250, 251, 252)]]);
+    }
+
+    #[test]
+    fn bad_ppm_files_are_errors() {
+        for data in [
+            &b""[..],
+            b"P3\n2 2\n",
+            b"P5\n1 1\n255\n0",
+            b"P3\n2 2\n255\n1 2 3 4 5 6\n",
+            b"P6\n2 2\n255\n\x01\x02\x03",
+            b"P6\n4000000000 4000000000\n255\n\x01\x02\x03",
+            b"P3\n1 1\n255\n1 2 red\n",
+            b"P3\n1 1\n255\n1 2 4000000000\n",
+            b"P3\n0 1\n255\n",
+            b"P3\n1 1\n256\n1 2 3\n",
+        ] {
+            assert!(decode_ppm(data, "test.ppm").is_err(), "{:?}", String::from_utf8_lossy(data));
+        }
+    }
+
+    /// a 2 by 2 image supersampled 4 times, with only the sample in the bottom right corner of
+    /// the top left pixel lit
+    fn one_bright_sample(filter: Filter) -> Vec<Vec<Color>> {
+        let mut image = Image::new(2, 2);
+        image.set_antialias(4, filter);
+        image.resolve(
+            |i, v| if (i, v) == (3, 3) { [255.0 * 16.0; 3] } else { [0.0; 3] },
+            |[r, g, b]| Color::new_color(r.round().min(255.0) as u8, g.round().min(255.0) as u8, b.round().min(255.0) as u8),
+        )
+    }
+
+    #[test]
+    fn box_filter_averages_each_pixel() {
+        let mut image = Image::new(3, 2);
+        image.set_antialias(2, Filter::Box);
+        let pixels = image.resolve(|i, v| [(i * 6 + v) as f32, 0.0, 0.0], |[r, _, _]| Color::new_color((r * 4.0) as u8, 0, 0));
+        let reds: Vec<Vec<u8>> = pixels.iter().map(|row| row.iter().map(|pixel| pixel.r).collect()).collect();
+        // the samples of the top left pixel are 0, 1, 6 and 7
+        assert_eq!(reds, [[14, 22, 30], [62, 70, 78]]);
+        assert_eq!(channels(&one_bright_sample(Filter::Box)), [[(255, 255, 255), (0, 0, 0)], [(0, 0, 0), (0, 0, 0)]]);
+    }
+
+    #[test]
+    fn tent_filter_keeps_flat_colors_and_reaches_neighbors() {
+        let mut image = Image::new(3, 3);
+        image.set_antialias(3, Filter::Tent);
+        let pixels = image.resolve(|_, _| [0.25, 0.5, 1.0], |[r, g, b]| Color::new_color((r * 100.0).round() as u8, (g * 100.0).round() as u8, (b * 100.0).round() as u8));
+        assert!(channels(&pixels).iter().flatten().all(|pixel| *pixel == (25, 50, 100)));
+
+        let pixels = channels(&one_bright_sample(Filter::Tent));
+        assert!(pixels[0][0].0 > pixels[0][1].0);
+        assert_eq!(pixels[0][1], pixels[1][0]);
+        assert!(pixels[0][1].0 > pixels[1][1].0 && pixels[1][1].0 > 0);
+    }
+}
@@ -0,0 +1,619 @@
+//! Runs a parsed script, once for every frame of the animation.
+
+use crate::ast::{Axis, Background, Command, Detail, Name, Script, Statement, Value, VaryCurve};
+use crate::cli::Options;
+use crate::color::Color;
+use crate::consts;
+use crate::error::MdlError;
+use crate::image::{self, make_animation, read_ppm, Image, LineStyle, Preview, Shading};
+use crate::matrix::{CurveType, Mat4, VertexBuffer};
+use crate::mesh::{Mesh, MeshCache};
+use crate::web::{write_viewer, WebFrame};
+use crate::ReflectionValue;
+use shunting::*;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+use std::process::Command as Process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use strfmt::strfmt;
+
+/// knob values of one frame
+pub type Knobs = HashMap<String, f32>;
+
+/// what the statements can see of the frame being drawn
+pub struct Frame<'a> {
+    pub number: usize,
+    pub knobs: &'a Knobs,
+    /// the knobs and frame_num, for working out expressions
+    variables: MathContext,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(number: usize, knobs: &'a Knobs) -> Frame<'a> {
+        let variables = MathContext::new();
+        for (name, value) in knobs {
+            variables.setvar(name, MathOp::Number(*value as f64));
+        }
+        variables.setvar("frame_num", MathOp::Number(number as f64));
+        Frame { number, knobs, variables }
+    }
+
+    pub fn value(&self, value: &Value) -> Result<f32, MdlError> {
+        match value {
+            Value::Number(number) => Ok(*number),
+            Value::Expression(expression) => self
+                .variables
+                .eval(&expression.rpn)
+                .map(|result| result as f32)
+                .map_err(|error| MdlError::with_span(format!("Unable to work out {} in frame {}: {}", expression.text, self.number, error), &expression.span)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Constant {
+    pub ambient_reflect: ReflectionValue,
+    pub diffuse_reflect: ReflectionValue,
+    pub specular_reflect: ReflectionValue,
+}
+
+impl Constant {
+    /// takes the reflection values in the order constants are written, red then green then blue
+    fn new(reflect: [f32; 9], scale: f32) -> Constant {
+        let [ambient_red, diffuse_red, specular_red, ambient_green, diffuse_green, specular_green, ambient_blue, diffuse_blue, specular_blue] =
+            reflect.map(|value| value * scale);
+        Constant {
+            ambient_reflect: ReflectionValue::new_values(ambient_red, ambient_green, ambient_blue),
+            diffuse_reflect: ReflectionValue::new_values(diffuse_red, diffuse_green, diffuse_blue),
+            specular_reflect: ReflectionValue::new_values(specular_red, specular_green, specular_blue),
+        }
+    }
+}
+
+/// how many steps curved shapes are drawn with
+#[derive(Clone, Copy)]
+enum Steps {
+    /// STEP_2D for circles and curves, STEP_3D for spheres and tori
+    Default,
+    Fixed(i32),
+    /// from how long the shape is around on the screen
+    Auto,
+}
+
+/// everything a frame draws into, reset between frames
+#[derive(Clone)]
+pub struct Interpreter {
+    pub screen: Image,
+    color: Color,
+    edges: VertexBuffer,
+    polygons: Mesh,
+    cstack: Vec<Mat4>,
+    csystems: HashMap<String, Mat4>,
+    constants_store: HashMap<String, Constant>,
+    /// whether frames are written by the frame loop instead of save and display
+    animated: bool,
+    /// where the depth images of an animation go, kept apart so the gif leaves them out
+    depth_dir: String,
+    preview: Preview,
+    detail: Steps,
+    /// spheres and tori already tessellated, kept from one frame to the next
+    meshes: MeshCache,
+}
+
+impl Interpreter {
+    /// sets up a canvas with the script's render settings
+    pub fn new(script: &Script, options: &Options, animated: bool) -> Result<Interpreter, MdlError> {
+        let settings = &script.settings;
+        let mut screen = Image::new(options.width, options.height);
+        if let Some(background) = &settings.background {
+            let background_value = match &background.node {
+                Background::Solid(color) => image::Background::Solid(*color),
+                Background::Gradient(top, bottom) => image::Background::Gradient(*top, *bottom),
+                Background::Picture(filename) => {
+                    image::Background::Picture(read_ppm(filename).map_err(|error| MdlError::with_span(error, &background.span))?)
+                }
+            };
+            screen.set_background(background_value);
+        }
+        if let Some((factor, filter)) = settings.antialias {
+            screen.set_antialias(factor, filter);
+        }
+        if let Some(tone_map) = settings.tone_map {
+            screen.set_hdr(tone_map, settings.exposure);
+        } else if settings.exposure != 0.0 {
+            warn!("exposure only applies with hdr on, so it will be ignored");
+        }
+        Ok(Interpreter {
+            screen,
+            color: Color::new_color(0, 255, 0),
+            edges: VertexBuffer::new(),
+            polygons: Mesh::new(),
+            cstack: vec![Mat4::identity()],
+            csystems: HashMap::new(),
+            constants_store: HashMap::new(),
+            animated,
+            depth_dir: depth_directory(&options.output_dir, settings.basename.as_deref().unwrap_or("output")),
+            preview: options.preview,
+            detail: Steps::Default,
+            meshes: MeshCache::new(),
+        })
+    }
+
+    /// runs one statement with the knob values of the given frame
+    pub fn execute(&mut self, statement: &Statement, frame: &Frame) -> Result<(), MdlError> {
+        let span = &statement.span;
+        match &statement.node {
+            Command::Constants { name, knob, reflect } => {
+                let scale = match knob {
+                    Some(knob) => *frame
+                        .knobs
+                        .get(&knob.node)
+                        .ok_or_else(|| MdlError::with_span(format!("knob {} has no value in frame {}", knob.node, frame.number), &knob.span))?,
+                    None => 1.0,
+                };
+                self.constants_store.insert(name.clone(), Constant::new(values(frame, reflect)?, scale));
+            }
+            Command::Push(coord_system) => {
+                let matrix = match coord_system {
+                    Some(name) => *self.find_coord_system(name)?,
+                    None => *self.cstack.last().unwrap(),
+                };
+                self.cstack.push(matrix);
+            }
+            Command::Pop => {
+                if self.cstack.len() <= 1 {
+                    return Err(MdlError::with_span("pop without a matching push", span));
+                }
+                self.cstack.pop();
+            }
+            Command::Move { x, y, z, knob } => {
+                let translate = Mat4::make_translate_with_scale(frame.value(x)?, frame.value(y)?, frame.value(z)?, knob_scale(knob, frame.knobs));
+                self.transform(translate);
+            }
+            Command::Rotate { axis, degrees, knob } => {
+                let rot_amount = frame.value(degrees)? * knob_scale(knob, frame.knobs);
+                let rot = match axis {
+                    Axis::X => Mat4::make_rot_x(rot_amount),
+                    Axis::Y => Mat4::make_rot_y(rot_amount),
+                    Axis::Z => Mat4::make_rot_z(rot_amount),
+                };
+                self.transform(rot);
+            }
+            Command::Scale { x, y, z, knob } => {
+                let scale = Mat4::make_scale_with_scale(frame.value(x)?, frame.value(y)?, frame.value(z)?, knob_scale(knob, frame.knobs));
+                self.transform(scale);
+            }
+            Command::Sphere { constants, x, y, z, radius, coord_system } => {
+                let (x, y, z, radius) = (frame.value(x)?, frame.value(y)?, frame.value(z)?, frame.value(radius)?);
+                let steps = self.steps(2.0 * PI * radius, coord_system, consts::STEP_3D)?;
+                let mesh = self.meshes.sphere(steps);
+                self.polygons.add_mesh(&mesh, x, y, z, radius);
+                self.draw_solid(constants, coord_system)?;
+            }
+            Command::Box { constants, x, y, z, width, height, depth, coord_system } => {
+                self.polygons.add_box(frame.value(x)?, frame.value(y)?, frame.value(z)?, frame.value(width)?, frame.value(height)?, frame.value(depth)?);
+                self.draw_solid(constants, coord_system)?;
+            }
+            Command::Torus { constants, x, y, z, inner_radius, outer_radius, coord_system } => {
+                let (x, y, z) = (frame.value(x)?, frame.value(y)?, frame.value(z)?);
+                let (inner_radius, outer_radius) = (frame.value(inner_radius)?, frame.value(outer_radius)?);
+                // the circle through the middle of the tube is the longest one around it
+                let steps = self.steps(2.0 * PI * (inner_radius.abs() + outer_radius.abs()), coord_system, consts::STEP_3D)?;
+                let (mesh, size) = self.meshes.torus(inner_radius, outer_radius, steps);
+                self.polygons.add_mesh(&mesh, x, y, z, size);
+                self.draw_solid(constants, coord_system)?;
+            }
+            Command::Display => {
+                if !self.animated {
+                    self.screen.display(self.preview).map_err(|error| MdlError::with_span(error, span))?;
+                }
+            }
+            Command::Save(filename) => {
+                if !self.animated {
+                    self.screen.create_file(filename).map_err(|error| MdlError::with_span(error, span))?;
+                    // ppm and png are written directly, anything else is converted from the ppm
+                    let extension = Path::new(filename).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+                    if !matches!(extension.as_deref(), Some("ppm") | Some("png")) {
+                        let status = Process::new("magick")
+                            .arg("convert")
+                            .arg(filename)
+                            .arg(filename)
+                            .status();
+                        if !status.is_ok_and(|status| status.success()) {
+                            return Err(MdlError::with_span(format!("failed to convert {} to the desired format with magick", filename), span));
+                        }
+                    }
+                }
+            }
+            Command::SaveDepth(filename) => {
+                if !self.animated {
+                    self.screen.create_depth_file(filename).map_err(|error| MdlError::with_span(error, span))?;
+                } else {
+                    // one depth image per frame, numbered like the animation frames
+                    let (stem, extension) = filename.rsplit_once('.').unwrap_or((filename, "ppm"));
+                    fs::create_dir_all(&self.depth_dir).map_err(|error| MdlError::with_span(format!("unable to create {} because {}", self.depth_dir, error), span))?;
+                    self.screen
+                        .create_depth_file(&format!("{}/{}{:04}.{}", self.depth_dir, stem, frame.number, extension))
+                        .map_err(|error| MdlError::with_span(error, span))?;
+                }
+            }
+            Command::Line { x0, y0, z0, x1, y1, z1 } => {
+                self.edges.add_edge(frame.value(x0)?, frame.value(y0)?, frame.value(z0)?, frame.value(x1)?, frame.value(y1)?, frame.value(z1)?);
+                self.draw_edges();
+            }
+            Command::Circle { x, y, z, radius } => {
+                let (x, y, z, radius) = (frame.value(x)?, frame.value(y)?, frame.value(z)?, frame.value(radius)?);
+                let steps = self.steps(2.0 * PI * radius, &None, consts::STEP_2D)?;
+                self.edges.add_circle(x, y, z, radius, steps);
+                self.draw_edges();
+            }
+            Command::Curve { curve_type, points } => {
+                let [x0, y0, x1, y1, x2, y2, x3, y3] = values(frame, points)?;
+                let steps = self.steps(curve_length(curve_type, [x0, y0, x1, y1, x2, y2, x3, y3]), &None, consts::STEP_2D)?;
+                self.edges.add_curve(x0, y0, x1, y1, x2, y2, x3, y3, steps, curve_type);
+                self.draw_edges();
+            }
+            Command::Detail(Detail::Steps(steps)) => {
+                let steps = frame.value(steps)?.round() as i32;
+                if !(consts::MIN_STEP..=consts::MAX_STEP).contains(&steps) {
+                    return Err(MdlError::with_span(
+                        format!("detail must be between {} and {}, but is {} in frame {}", consts::MIN_STEP, consts::MAX_STEP, steps, frame.number),
+                        span,
+                    ));
+                }
+                self.detail = Steps::Fixed(steps);
+            }
+            Command::Detail(Detail::Auto) => self.detail = Steps::Auto,
+            Command::LineStyle(style) => self.screen.line_style = *style,
+            Command::LineWidth(width) => {
+                let width = frame.value(width)?;
+                if width <= 0.0 {
+                    return Err(MdlError::with_span(format!("line width must be positive, but is {} in frame {}", width, frame.number), span));
+                }
+                self.screen.line_width = width;
+            }
+            Command::Shading(shading) => self.screen.shading = *shading,
+            Command::SaveCoordSystem(name) => {
+                self.csystems.insert(name.clone(), *self.cstack.last().unwrap());
+            }
+            Command::If { condition, then_statements, else_statements } => {
+                let left = frame.value(&condition.left)?;
+                let holds = match &condition.comparison {
+                    Some((comparison, right)) => comparison.holds(left, frame.value(right)?),
+                    None => left != 0.0,
+                };
+                for statement in if holds { then_statements } else { else_statements } {
+                    self.execute(statement, frame)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// the coordinate stack, with the current coordinate system last
+    pub fn stack(&self) -> &[Mat4] {
+        &self.cstack
+    }
+
+    /// writes the finished frame and clears everything for the next one
+    pub fn render_reset_image_canvas(&mut self, filename: &str) -> Result<(), String> {
+        self.screen.create_file(filename)?;
+        info!("Rendering {}...", filename);
+        self.screen.clear();
+        self.screen.line_style = LineStyle::Sharp;
+        self.screen.line_width = 1.0;
+        self.screen.shading = Shading::Flat;
+        self.detail = Steps::Default;
+        self.edges.clear();
+        self.polygons.clear();
+        self.cstack = vec![Mat4::identity()];
+        self.csystems.clear();
+        self.constants_store.clear();
+        Ok(())
+    }
+
+    /// applies a transformation to the top of the coordinate stack
+    fn transform(&mut self, transformation: Mat4) {
+        let top = self.cstack.last_mut().unwrap();
+        *top = top.multiply(&transformation);
+    }
+
+    /// moves the edges into place, draws them, then empties the edge list
+    fn draw_edges(&mut self) {
+        self.edges.transform(self.cstack.last().unwrap());
+        self.screen.draw_lines(&self.edges, &self.color);
+        self.edges.clear();
+    }
+
+    /// how many steps to go around a shape with, given how long it is around before being
+    /// moved into place and the number to use when detail was never given
+    fn steps(&self, length: f32, coord_system: &Option<Name>, default: i32) -> Result<i32, MdlError> {
+        Ok(match self.detail {
+            Steps::Default => default,
+            Steps::Fixed(steps) => steps,
+            Steps::Auto => {
+                let length = length.abs() * self.placement(coord_system)?.scale();
+                ((length / consts::AUTO_SEGMENT_LENGTH).ceil() as i32).clamp(consts::MIN_STEP, consts::MAX_STEP)
+            }
+        })
+    }
+
+    /// the coordinate system a solid is drawn in
+    fn placement(&self, coord_system: &Option<Name>) -> Result<Mat4, MdlError> {
+        Ok(match coord_system {
+            Some(name) => *self.find_coord_system(name)?,
+            None => *self.cstack.last().unwrap(),
+        })
+    }
+
+    /// moves the polygons into place, draws them, then empties the polygon list
+    fn draw_solid(&mut self, constants: &Option<Name>, coord_system: &Option<Name>) -> Result<(), MdlError> {
+        let matrix = self.placement(coord_system)?;
+        self.polygons.transform(&matrix);
+        let (ambient_reflect, diffuse_reflect, specular_reflect) = match constants {
+            Some(name) => {
+                let lighting_constants = self.constants_store.get(&name.node).ok_or_else(|| {
+                    MdlError::with_span(format!("no constants named {} have been defined", name.node), &name.span)
+                })?;
+                (lighting_constants.ambient_reflect, lighting_constants.diffuse_reflect, lighting_constants.specular_reflect)
+            }
+            None => (consts::AMBIENT_REFLECT, consts::DIFFUSE_REFLECT, consts::SPECULAR_REFLECT),
+        };
+        let (mut view, mut point_light_location) = (consts::VIEW, consts::POINT_LIGHT_LOCATION);
+        self.screen.draw_polygons(
+            &self.polygons,
+            &self.color,
+            &mut view,
+            &consts::AMBIENT_COLOR,
+            &mut point_light_location,
+            &consts::POINT_LIGHT_COLOR,
+            &ambient_reflect,
+            &diffuse_reflect,
+            &specular_reflect,
+        );
+        self.polygons.clear();
+        Ok(())
+    }
+
+    /// looks up a saved coordinate system
+    fn find_coord_system(&self, name: &Name) -> Result<&Mat4, MdlError> {
+        self.csystems
+            .get(&name.node)
+            .ok_or_else(|| MdlError::with_span(format!("no coordinate system named {} has been saved", name.node), &name.span))
+    }
+}
+
+/// works out several values at once, like the reflection values of constants
+fn values<const N: usize>(frame: &Frame, values: &[Value; N]) -> Result<[f32; N], MdlError> {
+    let mut numbers = [0.0; N];
+    for (number, value) in numbers.iter_mut().zip(values) {
+        *number = frame.value(value)?;
+    }
+    Ok(numbers)
+}
+
+/// the length of the lines joining the control points of a curve, which the curve itself is
+/// never longer than. A hermite's are a third of its rates of change away from its ends
+fn curve_length(curve_type: &CurveType, [x0, y0, x1, y1, x2, y2, x3, y3]: [f32; 8]) -> f32 {
+    let corners = match curve_type {
+        CurveType::Bezier => [(x0, y0), (x1, y1), (x2, y2), (x3, y3)],
+        CurveType::Hermite => [(x0, y0), (x0 + x2 / 3.0, y0 + y2 / 3.0), (x1 - x3 / 3.0, y1 - y3 / 3.0), (x1, y1)],
+    };
+    corners.windows(2).map(|pair| (pair[1].0 - pair[0].0).hypot(pair[1].1 - pair[0].1)).sum()
+}
+
+/// a knob scales its transformation, and a knob that has no value this frame flattens it
+fn knob_scale(knob: &Option<Name>, knobs: &Knobs) -> f32 {
+    match knob {
+        Some(knob) => knobs.get(&knob.node).copied().unwrap_or(0.0),
+        None => 1.0,
+    }
+}
+
+/// works out the value of every knob in every frame from the script's vary commands
+pub fn compute_knobs(script: &Script) -> Result<Vec<Knobs>, MdlError> {
+    let frame_total = script.settings.frames.as_ref().map_or(1, |frames| frames.node);
+    let mut frames: Vec<Knobs> = vec![HashMap::new(); frame_total];
+    for variable in &script.variables {
+        for frame in frames.iter_mut() {
+            frame.insert(variable.node.name.clone(), variable.node.value);
+        }
+    }
+    for vary in &script.varies {
+        if script.settings.frames.is_none() {
+            return Err(MdlError::with_span("vary used without frame numbers included", &vary.span));
+        }
+        let start_frame = vary.node.start_frame;
+        let end_frame = vary.node.end_frame;
+        if end_frame >= frames.len() {
+            return Err(MdlError::with_span(format!("vary ends at frame {} but there are only {} frames", end_frame, frames.len()), &vary.span));
+        }
+        let knob_name = &vary.node.knob;
+        let frame_count = end_frame - start_frame;
+        match &vary.node.curve {
+            VaryCurve::Linear { start_value, end_value, power } => {
+                let (start_value, end_value, power_used) = (*start_value, *end_value, *power);
+                let mut current_value = start_value;
+                let change_in_value = (end_value - start_value) / frame_count as f32;
+                for (frame_num, frame) in (start_frame..).zip(&mut frames[start_frame..=end_frame]) {
+                    if power_used == 1.0 {
+                        frame.insert(knob_name.clone(), current_value);
+                        current_value += change_in_value;
+                    } else if end_value - start_value == 0.0 {
+                        frame.insert(knob_name.clone(), start_value);
+                    } else {
+                        let frame_result = ((1.0 / frame_count as f32) * (frame_num - start_frame) as f32).powf(power_used);
+                        frame.insert(knob_name.clone(), frame_result);
+                    }
+                }
+            }
+            VaryCurve::Equation { equation, min_value, max_value } => {
+                let mut fmt_map = HashMap::new();
+                for (frame_num, frame) in (start_frame..).zip(&mut frames[start_frame..=end_frame]) {
+                    fmt_map.insert("frame_num".to_string(), frame_num);
+                    let equation_error = |error: String| MdlError::with_span(format!("Unable to process mafs: {}", error), &equation.span);
+                    let filled_in = strfmt(&equation.node, &fmt_map).map_err(|error| equation_error(error.to_string()))?;
+                    let expr = ShuntingParser::parse_str(&filled_in).map_err(equation_error)?;
+                    let result = MathContext::new().eval(&expr).map_err(equation_error)? as f32;
+                    if result > *max_value {
+                        frame.insert(knob_name.clone(), *max_value);
+                    } else if result < *min_value {
+                        frame.insert(knob_name.clone(), *min_value);
+                    } else {
+                        frame.insert(knob_name.clone(), result);
+                    }
+                }
+            }
+        }
+    }
+    Ok(frames)
+}
+
+/// renders a script, writing either its saves, a still, or every frame of its animation
+pub fn run(script: &Script, options: &Options) -> Result<(), MdlError> {
+    let frames = compute_knobs(script)?;
+    let (first_frame, last_frame) = options.frame_range.unwrap_or((0, frames.len() - 1));
+    if last_frame >= frames.len() {
+        return Err(MdlError::new(format!("frame {} was asked for, but the script only has {} frames", last_frame, frames.len())));
+    }
+    let animating = frames.len() > 1 && !options.still_only;
+    let extension = options.format.extension();
+    let basename = script.settings.basename.as_deref().unwrap_or("output");
+    let interpreter = Interpreter::new(script, options, animating)?;
+    let web_frames = if animating {
+        clean_animation_directory(&options.output_dir, basename, extension)?;
+        render_animation(script, options, interpreter, &frames, (first_frame, last_frame))?
+    } else {
+        // a still, or a script without frames, only ever draws one frame, so the threads
+        // go to filling in its triangles instead
+        let mut interpreter = interpreter;
+        interpreter.screen.threads = options.jobs;
+        verbose!("Knobs for frame {}: {:?}", first_frame, frames[first_frame]);
+        let frame = Frame::new(first_frame, &frames[first_frame]);
+        for statement in &script.statements {
+            interpreter.execute(statement, &frame)?;
+        }
+        if options.still_only {
+            fs::create_dir_all(&options.output_dir).map_err(|error| format!("unable to create {} because {}", options.output_dir, error))?;
+            let filename = format!("{}/{}.{}", options.output_dir, basename, extension);
+            info!("Rendering {}...", filename);
+            interpreter.screen.create_file(&filename)?;
+        }
+        let web_frame = script.settings.web.then(|| WebFrame::new(first_frame, &interpreter.screen.output_pixels(), &frames[first_frame]));
+        web_frame.into_iter().collect()
+    };
+    // written before the gif, which needs convert to be installed
+    if script.settings.web {
+        fs::create_dir_all(&options.output_dir).map_err(|error| format!("unable to create {} because {}", options.output_dir, error))?;
+        write_viewer(&format!("{}/{}.html", options.output_dir, basename), basename, &web_frames)?;
+    }
+    if animating {
+        make_animation(&options.output_dir, basename, extension)?;
+    }
+    Ok(())
+}
+
+/// renders and writes the frames from first to last, inclusive, on options.jobs threads.
+/// Every frame starts from a clean canvas, so each thread draws into its own copy of the
+/// interpreter and takes whichever frame is next. Returns the frames for the web page in order
+fn render_animation(script: &Script, options: &Options, interpreter: Interpreter, frames: &[Knobs], (first_frame, last_frame): (usize, usize)) -> Result<Vec<WebFrame>, MdlError> {
+    let extension = options.format.extension();
+    let basename = script.settings.basename.as_deref().unwrap_or("output");
+    let next_frame = AtomicUsize::new(first_frame);
+    let failed = AtomicBool::new(false);
+    let render = |mut interpreter: Interpreter| -> Result<Vec<WebFrame>, (usize, MdlError)> {
+        let mut web_frames = Vec::new();
+        while !failed.load(Ordering::Relaxed) {
+            let frame_num = next_frame.fetch_add(1, Ordering::Relaxed);
+            if frame_num > last_frame {
+                break;
+            }
+            let knobs = &frames[frame_num];
+            verbose!("Knobs for frame {}: {:?}", frame_num, knobs);
+            let frame = Frame::new(frame_num, knobs);
+            let rendered = script.statements.iter().try_for_each(|statement| interpreter.execute(statement, &frame)).and_then(|()| {
+                if script.settings.web {
+                    web_frames.push(WebFrame::new(frame_num, &interpreter.screen.output_pixels(), knobs));
+                }
+                let filename = format!("{}/{}{:04}.{}", options.output_dir, basename, frame_num, extension);
+                Ok(interpreter.render_reset_image_canvas(&filename)?)
+            });
+            if let Err(error) = rendered {
+                failed.store(true, Ordering::Relaxed);
+                return Err((frame_num, error));
+            }
+        }
+        Ok(web_frames)
+    };
+    let jobs = options.jobs.min(last_frame - first_frame + 1);
+    let results: Vec<_> = thread::scope(|scope| {
+        let render = &render;
+        let workers: Vec<_> = (1..jobs)
+            .map(|_| {
+                let interpreter = interpreter.clone();
+                scope.spawn(move || render(interpreter))
+            })
+            .collect();
+        let mut results = vec![render(interpreter)];
+        results.extend(workers.into_iter().map(|worker| worker.join().unwrap()));
+        results
+    });
+    // frames are handed out in order, so every frame before the earliest failure was still
+    // drawn and that failure is the one rendering them one at a time would have stopped at
+    let mut web_frames = Vec::new();
+    let mut first_error: Option<(usize, MdlError)> = None;
+    for result in results {
+        match result {
+            Ok(frames) => web_frames.extend(frames),
+            Err((frame_num, error)) => {
+                if first_error.as_ref().is_none_or(|(earliest, _)| frame_num < *earliest) {
+                    first_error = Some((frame_num, error));
+                }
+            }
+        }
+    }
+    if let Some((_, error)) = first_error {
+        return Err(error);
+    }
+    web_frames.sort_by_key(|frame| frame.number);
+    Ok(web_frames)
+}
+
+/// makes sure the output directory exists without frames left over from an earlier render
+fn clean_animation_directory(output_dir: &str, basename: &str, extension: &str) -> Result<(), String> {
+    fs::create_dir_all(output_dir).map_err(|error| format!("unable to create {} because {}", output_dir, error))?;
+    let entries = fs::read_dir(output_dir).map_err(|error| format!("unable to read {} because {}", output_dir, error))?;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let frame_number = name.strip_prefix(basename).and_then(|rest| rest.strip_suffix(&*format!(".{}", extension)));
+        if frame_number.is_some_and(is_frame_number) {
+            fs::remove_file(entry.path()).map_err(|error| format!("unable to delete {} because {}", name, error))?;
+        }
+    }
+    // depth images can be named anything, so every numbered file goes
+    let depth_dir = depth_directory(output_dir, basename);
+    if let Ok(entries) = fs::read_dir(&depth_dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let stem = name.rsplit_once('.').map_or(name.as_str(), |(stem, _)| stem);
+            if stem.len().checked_sub(4).and_then(|start| stem.get(start..)).is_some_and(is_frame_number) {
+                fs::remove_file(entry.path()).map_err(|error| format!("unable to delete {}/{} because {}", depth_dir, name, error))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// whether text is the four digit number put after the name of each frame
+fn is_frame_number(text: &str) -> bool {
+    text.len() == 4 && text.chars().all(|c| c.is_ascii_digit())
+}
+
+/// the directory the depth images of an animation are written to, which has no extension
+/// so make_animation never picks it up
+fn depth_directory(output_dir: &str, basename: &str) -> String {
+    format!("{}/{}_depth", output_dir, basename)
+}
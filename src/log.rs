@@ -0,0 +1,48 @@
+//! Console output shared by the whole renderer, filtered by the verbosity chosen on the
+//! command line. Errors are not logged through here, they are returned up to main.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub enum Verbosity {
+    /// nothing but errors
+    Quiet = 0,
+    /// progress and warnings
+    Normal = 1,
+    /// also timings and per frame details
+    Verbose = 2,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Verbose) {
+            println!($($arg)*);
+        }
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Verbosity::Normal) {
+            eprintln!("WARNING: {}", format_args!($($arg)*));
+        }
+    };
+}
@@ -1,18 +1,29 @@
+#[macro_use]
+mod log;
+mod ast;
+mod check;
+mod cli;
 mod color;
 mod draw;
+mod error;
 mod gmath;
 mod image;
+mod interpreter;
 mod matrix;
+mod mesh;
 mod parser;
+mod png;
 mod reflect;
+mod repl;
+mod terminal;
+mod watch;
+mod web;
 use color::Color;
 use image::Image;
 use std::time::Instant;
-use matrix::CurveType;
-use matrix::Matrix;
-use parser::parse;
 use reflect::ReflectionValue;
 use std::env;
+use std::process::ExitCode;
 
 extern crate pest;
 #[macro_use]
@@ -31,11 +42,83 @@ pub mod consts {
     pub const VIEW: [f32; 3] = [0.0, 0.0, 1.0];
     pub const STEP_2D: i32 = 100;
     pub const STEP_3D: i32 = 100;
+    /// the fewest and most steps detail can ask for
+    pub const MIN_STEP: i32 = 3;
+    pub const MAX_STEP: i32 = 1000;
+    /// how long each straight piece of a shape is on the screen with detail auto, in pixels
+    pub const AUTO_SEGMENT_LENGTH: f32 = 5.0;
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
+    let options = match cli::parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("ERROR: {}\n\n{}", error, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if options.help {
+        println!("{}", cli::USAGE);
+        return ExitCode::SUCCESS;
+    }
+    log::set_verbosity(options.verbosity);
+
+    if options.repl {
+        return match repl::run(&options) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("ERROR: {}", error);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if options.watch {
+        watch::watch(&options, render);
+    }
+    if render(&options, &mut Vec::new()) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// parses, checks and renders the script, printing what went wrong if anything did. Every
+/// file the render depends on is added to sources, as far as it got before failing
+fn render(options: &cli::Options, sources: &mut Vec<String>) -> bool {
     let time = Instant::now();
-    parse("spheretification.mdl");
-    println!("Render finished in {:?}", time.elapsed())
+    let script = match parser::parse(&options.script) {
+        Ok(script) => script,
+        Err(error) => {
+            sources.extend(error.span.as_ref().map(|span| span.file.clone()));
+            eprintln!("ERROR: {}", error);
+            return false;
+        }
+    };
+    sources.extend(script.sources.iter().cloned());
+    if let Some(ast::Background::Picture(filename)) = script.settings.background.as_ref().map(|background| &background.node) {
+        sources.push(filename.clone());
+    }
+    let problems = check::check(&script);
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("ERROR: {}\n", problem);
+        }
+        eprintln!("found {} problem{} in {}", problems.len(), if problems.len() == 1 { "" } else { "s" }, options.script);
+        return false;
+    }
+    if options.check_only {
+        info!("{} has no problems", options.script);
+        return true;
+    }
+    match interpreter::run(&script, options) {
+        Ok(()) => {
+            info!("Render finished in {:?}", time.elapsed());
+            true
+        }
+        Err(error) => {
+            eprintln!("ERROR: {}", error);
+            false
+        }
+    }
 }
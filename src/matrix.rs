@@ -1,195 +1,163 @@
 use std::fmt;
 use std::f32;
 
-#[derive(Clone)]
-pub struct Matrix{
-    pub(in crate) matrix_array: Vec<Vec<f32>>,
+/// a 4x4 transformation, kept on the stack since one is made for every move, rotate and scale
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Mat4{
+    pub(in crate) rows: [[f32; 4]; 4],
 }
 
+/// points one after another, in pairs for edges or in threes for triangles
+#[derive(Clone, Default)]
+pub struct VertexBuffer{
+    pub(in crate) points: Vec<[f32; 4]>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum CurveType{
     Bezier,
     Hermite
 }
 
-impl Matrix{
-    pub fn new(row: usize, col: usize) -> Matrix{
-        Matrix{matrix_array: vec![vec![0.0; col]; row],}
-    }
+impl Mat4{
+    pub const IDENTITY: Mat4 = Mat4{rows: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]};
 
-    pub fn multiply_by_num(&mut self, multiply_by: f32){
-        for i in 0..self.matrix_array.len(){
-            for v in 0..self.matrix_array[i].len(){
-                self.matrix_array[i][v] *= multiply_by;
-            }
-        }
+    pub fn identity() -> Mat4{
+        Mat4::IDENTITY
     }
-    
-    /// multiply m1 by the object that this is called on, modifying this object to be the product
-    /// 
-    /// m1 * this object -> this object
-    pub fn multiply_matrixes(&mut self, m1: &Matrix){
-        let mut matrix_result = Matrix::new(m1.matrix_array.len(), self.matrix_array[0].len());
-        for result_i in 0..matrix_result.matrix_array.len(){
-            for result_v in 0..matrix_result.matrix_array[result_i].len(){
-                for m2_down_num in 0..self.matrix_array.len(){
-                    matrix_result.matrix_array[result_i][result_v] += self.matrix_array[m2_down_num][result_v] * m1.matrix_array[result_i][m2_down_num];
+
+    /// this matrix times other, so other is applied to points first
+    pub fn multiply(&self, other: &Mat4) -> Mat4{
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate(){
+            for (v, value) in row.iter_mut().enumerate(){
+                for k in 0..4{
+                    *value += other.rows[k][v] * self.rows[i][k];
                 }
             }
         }
-        
-        *self = matrix_result;
-    }
-    
-    pub fn identity() -> Matrix{
-        let mut identity_matrix = Matrix::new(4,4);
-        for i in 0..identity_matrix.matrix_array.len(){
-            for v in 0..identity_matrix.matrix_array[0].len(){
-                if i == v{
-                    identity_matrix.matrix_array[i][v] = 1.0;
-                }else{
-                    identity_matrix.matrix_array[i][v] = 0.0;
-                }
+        Mat4{rows}
+    }
+
+    /// the point moved by this matrix
+    pub fn apply(&self, point: &[f32; 4]) -> [f32; 4]{
+        let mut result = [0.0; 4];
+        for (value, row) in result.iter_mut().zip(&self.rows){
+            for (coordinate, factor) in point.iter().zip(row){
+                *value += coordinate * factor;
             }
         }
-        return identity_matrix;
-    }
-    
-    pub fn print_matrix(&self){
-        println!("{}", self);
-    }
-    
-    pub fn make_translate(x: f32, y: f32, z: f32) -> Matrix{
-        return Matrix::make_translate_with_scale(x,y,z,1.0);
-    }
-
-    pub fn make_translate_with_scale(x: f32, y: f32, z: f32, scale: f32) -> Matrix{
-        let mut matrix = Matrix::identity();
-        matrix.matrix_array[0][3] = x * scale;
-        matrix.matrix_array[1][3] = y * scale;
-        matrix.matrix_array[2][3] = z * scale;
-        return matrix;
-    }
-    
-    pub fn make_scale_with_scale( x: f32, y: f32, z: f32, scale: f32) -> Matrix{
-        let mut matrix = Matrix::identity();
-        matrix.matrix_array[0][0] = x * scale;
-        matrix.matrix_array[1][1] = y * scale;
-        matrix.matrix_array[2][2] = z * scale;
-        return matrix;
-    }
-
-    pub fn make_scale( x: f32, y: f32, z: f32) -> Matrix{
-        return Matrix::make_scale_with_scale(x, y, z, 1.0);
-    }
-    
-    pub fn make_rot_x(mut theta: f32 ) -> Matrix{
-        let mut matrix = Matrix::identity();
-        theta = theta.to_radians();
-        matrix.matrix_array[1][1] = theta.cos();
-        matrix.matrix_array[1][2] = theta.sin() * -1.0;
-        matrix.matrix_array[2][1] = matrix.matrix_array[1][2] * -1.0;
-        matrix.matrix_array[2][2] = matrix.matrix_array[1][1];
-        return matrix;
-    }
-    
-    pub fn make_rot_y( mut theta: f32 ) -> Matrix{
-        let mut matrix = Matrix::identity();
-        theta = theta.to_radians();
-        matrix.matrix_array[0][0] = theta.cos();
-        matrix.matrix_array[0][2] = theta.sin();
-        matrix.matrix_array[2][0] = matrix.matrix_array[0][2] * -1.0;
-        matrix.matrix_array[2][2] = matrix.matrix_array[0][0];
-        return matrix;
-    }
-    
-    pub fn make_rot_z( mut theta: f32 ) -> Matrix{
-        let mut matrix = Matrix::identity();
-        theta = theta.to_radians();
-        matrix.matrix_array[0][0] = theta.cos();
-        matrix.matrix_array[0][1] = theta.sin() * -1.0;
-        matrix.matrix_array[1][0] = matrix.matrix_array[0][1] * -1.0;
-        matrix.matrix_array[1][1] = matrix.matrix_array[0][0];
-        return matrix;
-    }
-    
+        result
+    }
+
+    /// the most the matrix stretches anything, taken as the longest of its x, y and z axes
+    pub fn scale(&self) -> f32{
+        (0..3)
+            .map(|column| self.rows[..3].iter().map(|row| row[column].powi(2)).sum::<f32>().sqrt())
+            .fold(0.0, f32::max)
+    }
+
+    pub fn make_translate_with_scale(x: f32, y: f32, z: f32, scale: f32) -> Mat4{
+        let mut matrix = Mat4::identity();
+        matrix.rows[0][3] = x * scale;
+        matrix.rows[1][3] = y * scale;
+        matrix.rows[2][3] = z * scale;
+        matrix
+    }
+
+    pub fn make_scale_with_scale(x: f32, y: f32, z: f32, scale: f32) -> Mat4{
+        let mut matrix = Mat4::identity();
+        matrix.rows[0][0] = x * scale;
+        matrix.rows[1][1] = y * scale;
+        matrix.rows[2][2] = z * scale;
+        matrix
+    }
+
+    pub fn make_rot_x(theta: f32) -> Mat4{
+        let mut matrix = Mat4::identity();
+        let theta = theta.to_radians();
+        matrix.rows[1][1] = theta.cos();
+        matrix.rows[1][2] = -theta.sin();
+        matrix.rows[2][1] = -matrix.rows[1][2];
+        matrix.rows[2][2] = matrix.rows[1][1];
+        matrix
+    }
+
+    pub fn make_rot_y(theta: f32) -> Mat4{
+        let mut matrix = Mat4::identity();
+        let theta = theta.to_radians();
+        matrix.rows[0][0] = theta.cos();
+        matrix.rows[0][2] = theta.sin();
+        matrix.rows[2][0] = -matrix.rows[0][2];
+        matrix.rows[2][2] = matrix.rows[0][0];
+        matrix
+    }
+
+    pub fn make_rot_z(theta: f32) -> Mat4{
+        let mut matrix = Mat4::identity();
+        let theta = theta.to_radians();
+        matrix.rows[0][0] = theta.cos();
+        matrix.rows[0][1] = -theta.sin();
+        matrix.rows[1][0] = -matrix.rows[0][1];
+        matrix.rows[1][1] = matrix.rows[0][0];
+        matrix
+    }
+
     ///Returns: The correct 4x4 matrix that can be used
     ///to generate the coefiecients for a bezier curve
-    pub fn make_bezier() -> Matrix{
-        let mut matrix = Matrix::new(4,4);
-        matrix.matrix_array[0][0] = -1.0;
-        matrix.matrix_array[0][1] = 3.0;
-        matrix.matrix_array[0][2] = -3.0;
-        matrix.matrix_array[0][3] = 1.0;
-        matrix.matrix_array[1][0] = 3.0;
-        matrix.matrix_array[1][1] = -6.0;
-        matrix.matrix_array[1][2] = 3.0;
-        matrix.matrix_array[2][0] = -3.0;
-        matrix.matrix_array[2][1] = 3.0;
-        matrix.matrix_array[3][0] = 1.0;
-        return matrix;
+    pub fn make_bezier() -> Mat4{
+        Mat4{rows: [[-1.0, 3.0, -3.0, 1.0], [3.0, -6.0, 3.0, 0.0], [-3.0, 3.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0]]}
     }
+
     ///Returns: The correct 4x4 matrix that can be used
     ///to generate the coefiecients for a hermite curve
-    pub fn make_hermite() -> Matrix{
-        let mut matrix = Matrix::new(4,4);
-        matrix.matrix_array[0][0] = 2.0;
-        matrix.matrix_array[0][1] = -2.0;
-        matrix.matrix_array[0][2] = 1.0;
-        matrix.matrix_array[0][3] = 1.0;
-        matrix.matrix_array[1][0] = -3.0;
-        matrix.matrix_array[1][1] = 3.0;
-        matrix.matrix_array[1][2] = -2.0;
-        matrix.matrix_array[1][3] = -1.0;
-        matrix.matrix_array[2][2] = 1.0;
-        matrix.matrix_array[3][0] = 1.0;
-        return matrix;
-    }
-    
-    /// Inputs:   double p1
-    /// 
-    /// double p2
-    /// 
-    /// double p3
-    /// 
-    /// double p4
-    /// 
-    /// enum CurveType type
-    /// 
+    pub fn make_hermite() -> Mat4{
+        Mat4{rows: [[2.0, -2.0, 1.0, 1.0], [-3.0, 3.0, -2.0, -1.0], [0.0, 0.0, 1.0, 0.0], [1.0, 0.0, 0.0, 0.0]]}
+    }
+
     /// Returns:
-    /// 
-    /// A matrix containing the values for a, b, c and d of the
-    /// equation at^3 + bt^2 + ct + d for the curve defined
-    /// by p1, p2, p3 and p4.
-    
-    pub fn generate_curve_coefs( p0: f32, p1: f32, p2: f32, p3: f32, t: &CurveType ) -> Matrix{
-        let mut matrix = Matrix::new(4,1);
-        matrix.matrix_array[0][0] = p0;
-        matrix.matrix_array[1][0] = p1;
-        matrix.matrix_array[2][0] = p2; // r0 if hermite
-        matrix.matrix_array[3][0] = p3; // r1 if hermite
-        let curve_matrix: Matrix;
-        match t{
-            CurveType::Bezier=>{
-                curve_matrix =  Matrix::make_bezier();
-            }
-            CurveType::Hermite=>{
-                curve_matrix = Matrix::make_hermite();
-            }
-        }
-        matrix.multiply_matrixes(&curve_matrix);
-        return matrix;
+    ///
+    /// a, b, c and d of the equation at^3 + bt^2 + ct + d
+    /// for the curve defined by p0, p1, p2 and p3,
+    /// where p2 and p3 are the rates of change for a hermite curve
+    pub fn generate_curve_coefs(p0: f32, p1: f32, p2: f32, p3: f32, t: &CurveType) -> [f32; 4]{
+        let curve_matrix = match t{
+            CurveType::Bezier => Mat4::make_bezier(),
+            CurveType::Hermite => Mat4::make_hermite(),
+        };
+        curve_matrix.apply(&[p0, p1, p2, p3])
     }
 }
 
-impl fmt::Display for Matrix{
+impl fmt::Display for Mat4{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
-        let mut result: String = "".to_owned();
-        for i in 0..self.matrix_array.len(){
-            for v in 0..self.matrix_array[i].len(){
-                result.push_str(&(format!("{} ",self.matrix_array[i][v]).to_string()));
+        for row in &self.rows{
+            for value in row{
+                write!(f, "{} ", value)?;
             }
-            result.push_str("\n");
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl VertexBuffer{
+    pub fn new() -> VertexBuffer{
+        VertexBuffer{points: Vec::new()}
+    }
+
+    pub fn clear(&mut self){
+        self.points.clear();
+    }
+
+    pub fn add_point(&mut self, x: f32, y: f32, z: f32){
+        self.points.push([x, y, z, 1.0]);
+    }
+
+    /// moves every point by the matrix
+    pub fn transform(&mut self, matrix: &Mat4){
+        for point in &mut self.points{
+            *point = matrix.apply(point);
         }
-        write!(f, "{}", result)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,172 @@
+//! Triangle meshes, stored as each point once and the corners of every triangle as indexes into
+//! them. Spheres and tori are kept once they have been worked out, so a shape drawn again in a
+//! later frame, or somewhere else in the same one, only needs to be moved and scaled into place
+//! instead of going through all the trig again.
+
+use crate::matrix::{Mat4, VertexBuffer};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// how many shapes are kept before the cache starts over, since a knob that changes a torus'
+/// proportions every frame would otherwise add a new one each time
+const MAX_MESHES: usize = 64;
+
+/// points, and triangles joining them with their corners counter clockwise from the outside
+#[derive(Clone, Default)]
+pub struct Mesh {
+    pub(in crate) vertices: Vec<[f32; 4]>,
+    /// indexes into vertices, three for every triangle
+    pub(in crate) triangles: Vec<[u32; 3]>,
+}
+
+impl Mesh {
+    pub fn new() -> Mesh {
+        Mesh::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.triangles.clear();
+    }
+
+    /// adds a point and gives back its index
+    pub fn add_vertex(&mut self, x: f32, y: f32, z: f32) -> u32 {
+        self.vertices.push([x, y, z, 1.0]);
+        (self.vertices.len() - 1) as u32
+    }
+
+    /// adds the triangle joining three points already in the mesh, unless it has no area
+    /// and so could never be seen
+    pub fn add_polygon(&mut self, corners: [u32; 3]) {
+        if self.normal_of(corners) != [0.0; 3] {
+            self.triangles.push(corners);
+        }
+    }
+
+    /// moves every point by the matrix
+    pub fn transform(&mut self, matrix: &Mat4) {
+        for vertex in &mut self.vertices {
+            *vertex = matrix.apply(vertex);
+        }
+    }
+
+    /// adds a copy of another mesh, scaled by size and then centred on (cx, cy, cz)
+    pub fn add_mesh(&mut self, mesh: &Mesh, cx: f32, cy: f32, cz: f32, size: f32) {
+        let placement = Mat4::make_translate_with_scale(cx, cy, cz, 1.0).multiply(&Mat4::make_scale_with_scale(size, size, size, 1.0));
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend(mesh.vertices.iter().map(|vertex| placement.apply(vertex)));
+        self.triangles.extend(mesh.triangles.iter().map(|corners| corners.map(|corner| corner + offset)));
+    }
+
+    /// the normal of each point, adding up the normals of the triangles around it. Longer
+    /// normals come from bigger triangles, so those count for more
+    pub fn vertex_normals(&self) -> Vec<[f32; 3]> {
+        let mut normals = vec![[0.0; 3]; self.vertices.len()];
+        for index in 0..self.triangles.len() {
+            let normal = self.calculate_normal(index);
+            for corner in self.triangles[index] {
+                let sum: &mut [f32; 3] = &mut normals[corner as usize];
+                for (total, value) in sum.iter_mut().zip(normal) {
+                    *total += value;
+                }
+            }
+        }
+        normals
+    }
+}
+
+/// identifies a tessellated shape by everything that changes its points other than where it is
+/// and how big it is
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Shape {
+    Sphere { step: i32 },
+    /// the radii as bits, after dividing both by their total
+    Torus { circle_radius: u32, torus_radius: u32, step: i32 },
+}
+
+/// meshes made so far. They are shared, so cloning the interpreter for another thread or an
+/// undo step does not copy them
+#[derive(Clone, Default)]
+pub struct MeshCache {
+    meshes: HashMap<Shape, Arc<Mesh>>,
+}
+
+impl MeshCache {
+    pub fn new() -> MeshCache {
+        MeshCache::default()
+    }
+
+    /// a sphere of radius 1 with step points per semicircle. The points come from
+    /// generate_sphere the first time that step is used, with the ones at each pole joined
+    /// into one so the triangles around it share their normal
+    pub fn sphere(&mut self, step: i32) -> Arc<Mesh> {
+        self.get_or_make(Shape::Sphere { step }, || {
+            let points = VertexBuffer::generate_sphere(0.0, 0.0, 0.0, 1.0, step).points;
+            let step = step as usize;
+            let mut mesh = Mesh::new();
+            let [x, y, z, _] = points[0];
+            let north = mesh.add_vertex(x, y, z);
+            for rotation in 0..step {
+                for point in &points[rotation * (step + 1) + 1..rotation * (step + 1) + step] {
+                    mesh.add_vertex(point[0], point[1], point[2]);
+                }
+            }
+            let [x, y, z, _] = points[step];
+            let south = mesh.add_vertex(x, y, z);
+            // the last half circle is the first one again
+            let vertex = |rotation: usize, circle: usize| match circle {
+                0 => north,
+                circle if circle == step => south,
+                circle => (1 + rotation % step * (step - 1) + circle - 1) as u32,
+            };
+            for rotation in 0..step {
+                for circle in 0..step {
+                    mesh.add_polygon([vertex(rotation, circle), vertex(rotation, circle + 1), vertex(rotation + 1, circle)]);
+                    mesh.add_polygon([vertex(rotation, circle + 1), vertex(rotation + 1, circle + 1), vertex(rotation + 1, circle)]);
+                }
+            }
+            mesh
+        })
+    }
+
+    /// a torus with the given radii scaled so they add up to 1, and the size that scales it
+    /// back up to the radii asked for. The points come from generate_torus the first time it
+    /// is used, leaving out the last circle and the last point of each since they wrap around
+    /// to the first
+    pub fn torus(&mut self, circle_radius: f32, torus_radius: f32, step: i32) -> (Arc<Mesh>, f32) {
+        let size = circle_radius.abs() + torus_radius.abs();
+        let (circle_radius, torus_radius) = if size == 0.0 { (0.0, 0.0) } else { (circle_radius / size, torus_radius / size) };
+        let shape = Shape::Torus { circle_radius: circle_radius.to_bits(), torus_radius: torus_radius.to_bits(), step };
+        let mesh = self.get_or_make(shape, || {
+            let points = VertexBuffer::generate_torus(0.0, 0.0, 0.0, circle_radius, torus_radius, step).points;
+            let step = step as usize;
+            let mut mesh = Mesh::new();
+            for rotation in 0..step {
+                for point in &points[rotation * (step + 1)..rotation * (step + 1) + step] {
+                    mesh.add_vertex(point[0], point[1], point[2]);
+                }
+            }
+            let vertex = |rotation: usize, circle: usize| (rotation % step * step + circle % step) as u32;
+            for rotation in 0..step {
+                for circle in 0..step {
+                    mesh.add_polygon([vertex(rotation, circle), vertex(rotation, circle + 1), vertex(rotation + 1, circle)]);
+                    mesh.add_polygon([vertex(rotation + 1, circle), vertex(rotation, circle + 1), vertex(rotation + 1, circle + 1)]);
+                }
+            }
+            mesh
+        });
+        (mesh, size)
+    }
+
+    fn get_or_make(&mut self, shape: Shape, make: impl FnOnce() -> Mesh) -> Arc<Mesh> {
+        if let Some(mesh) = self.meshes.get(&shape) {
+            return Arc::clone(mesh);
+        }
+        if self.meshes.len() == MAX_MESHES {
+            self.meshes.clear();
+        }
+        let mesh = Arc::new(make());
+        self.meshes.insert(shape, Arc::clone(&mesh));
+        mesh
+    }
+}
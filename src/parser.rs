@@ -1,547 +1,815 @@
-use shunting::*;
-use strfmt::*;
-use std::process::Command;
+use crate::ast::{Axis, Background, Command, Comparison, Condition, Detail, Expression, Name, Script, Spanned, Statement, Value, Variable, Vary, VaryCurve};
 use crate::color::Color;
 use crate::consts;
-use crate::image::{Image, make_animation};
+use crate::error::{MdlError, Span};
+use crate::image::{Filter, LineStyle, Shading, ToneMap};
 use crate::matrix::CurveType;
-use crate::matrix::Matrix;
 use crate::pest::Parser;
-use crate::ReflectionValue;
+use shunting::{MathContext, ShuntingParser};
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::str::FromStr;
+use strfmt::strfmt;
 
 #[derive(Parser)]
 #[grammar = "mdl.pest"]
 struct MDLParser;
 
-#[derive(Debug)]
-struct Constant {
-    pub ambient_reflect: ReflectionValue,
-    pub diffuse_reflect: ReflectionValue,
-    pub specular_reflect: ReflectionValue,
-    pub red: f32,
-    pub green: f32,
-    pub blue: f32,
+/// one argument of a command, owned so macro bodies can be replayed after their file is read
+#[derive(Clone, Debug)]
+struct Token {
+    rule: Rule,
+    text: String,
+    span: Span,
 }
 
-impl Constant {
-    fn new(
-        ambient_red: f32,
-        diffuse_red: f32,
-        specular_red: f32,
-        ambient_green: f32,
-        diffuse_green: f32,
-        specular_green: f32,
-        ambient_blue: f32,
-        diffuse_blue: f32,
-        specular_blue: f32,
-        red: f32,
-        green: f32,
-        blue: f32,
-    ) -> Constant {
-        Constant {
-            ambient_reflect: ReflectionValue::new_values(ambient_red, ambient_green, ambient_blue),
-            diffuse_reflect: ReflectionValue::new_values(diffuse_red, diffuse_green, diffuse_blue),
-            specular_reflect: ReflectionValue::new_values(
-                specular_red,
-                specular_green,
-                specular_blue,
-            ),
-            red,
-            green,
-            blue
-        }
-    }
+type Tokens<'a> = slice::Iter<'a, Token>;
+
+/// a command as it was written, before its arguments are checked
+#[derive(Clone, Debug)]
+struct RawCommand {
+    rule: Rule,
+    span: Span,
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone)]
+struct Macro {
+    params: Vec<Token>,
+    body: Vec<RawCommand>,
+}
+
+/// macros defined by earlier sources, for reading several sources one after another as if
+/// they were one script
+#[derive(Clone, Default)]
+pub struct Macros(HashMap<String, Macro>);
+
+/// macro parameters bound to the arguments of the call being expanded
+type Bindings = HashMap<String, Token>;
+
+/// turns raw commands into the script, expanding includes and macros as they come
+struct Builder {
+    script: Script,
+    macros: HashMap<String, Macro>,
+    /// every file being read, by canonical path and by the name used for it
+    include_stack: Vec<(PathBuf, String)>,
+    /// macros being expanded, innermost last
+    call_stack: Vec<String>,
+    /// how many if blocks the commands being built are inside
+    if_depth: usize,
+    /// how many repeat and for blocks the commands being built are inside
+    loop_depth: usize,
+}
+
+/// reads an MDL file into a script, checking every argument along the way
+pub fn parse(fname: &str) -> Result<Script, MdlError> {
+    let instructions = read_script(fname).map_err(MdlError::new)?;
+    parse_source(fname, &instructions)
 }
 
-pub fn parse(fname: &str) {
-    let file = File::open(&fname).expect("Unable to open file");
+/// builds a script from MDL source, with fname used for error locations and includes
+pub fn parse_source(fname: &str, instructions: &str) -> Result<Script, MdlError> {
+    parse_source_with_macros(fname, instructions, &Macros::default()).map(|(script, _)| script)
+}
+
+/// builds a script from MDL source that can call the given macros, giving back the macros
+/// along with the ones the source defines
+pub fn parse_source_with_macros(fname: &str, instructions: &str, macros: &Macros) -> Result<(Script, Macros), MdlError> {
+    let mut builder = Builder {
+        script: Script { sources: vec![fname.to_owned()], ..Script::default() },
+        macros: macros.0.clone(),
+        include_stack: vec![(canonical_path(fname), fname.to_owned())],
+        call_stack: Vec::new(),
+        if_depth: 0,
+        loop_depth: 0,
+    };
+    let commands = read_commands(fname, instructions)?;
+    builder.expand(&commands, &Bindings::new())?;
+    Ok((builder.script, Macros(builder.macros)))
+}
+
+fn read_script(fname: &str) -> Result<String, String> {
+    let file = File::open(fname).map_err(|error| format!("Unable to open {} because {}", fname, error))?;
     let mut reader = BufReader::new(file);
     let mut instructions = String::new();
-    reader.read_to_string(&mut instructions).expect("Unable to read file");
-    
-    let commands = MDLParser::parse(Rule::IDENT_LIST, &instructions);
-    let mut screen = Image::new(500, 500);
-    let color = Color::new_color(0, 255, 0);
-    let mut edges = Matrix::new(0, 0);
-    let mut polygons = Matrix::new(0, 0);
-    let mut cstack = vec![Matrix::new(0, 0); 0];
-    let mut csystems: HashMap<&str, Matrix> = HashMap::new();
-    let mut constants_store = HashMap::new();
-    let mut basename = String::from("output");
-    let mut vary_exists = false;
-    let mut frames_exists = false;
-    let mut frames: Vec<HashMap<&str, f32>> = vec![HashMap::new()];
-
-    clean_animation_directory();
-    cstack.push(Matrix::identity());
-    // to get the frame rate
-    for pair in commands.clone() {
-        for command in pair {
-            let error_message = command.as_str();
-            match command.as_rule(){
-                Rule::FRAMES_D => {
-                    let mut command_contents = command.into_inner();
-                    frames = vec![HashMap::new(); command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid frame count at {}", error_message))];
-                    frames_exists = true;
+    reader.read_to_string(&mut instructions).map_err(|error| format!("Unable to read {} because {}", fname, error))?;
+    Ok(instructions)
+}
+
+/// files are compared by their canonical path so the same file reached two ways is still a cycle
+fn canonical_path(fname: &str) -> PathBuf {
+    fs::canonicalize(fname).unwrap_or_else(|_| PathBuf::from(fname))
+}
+
+/// runs the grammar over a file and keeps each command's arguments
+fn read_commands(fname: &str, instructions: &str) -> Result<Vec<RawCommand>, MdlError> {
+    let commands = MDLParser::parse(Rule::IDENT_LIST, instructions).map_err(|error| MdlError::from_pest(fname, instructions, error))?;
+    let mut raw_commands = Vec::new();
+    for command in commands {
+        if command.as_rule() == Rule::EOI {
+            continue;
+        }
+        let rule = command.as_rule();
+        let span = Span::from_pest(fname, &command.as_span());
+        let mut tokens = Vec::new();
+        for argument in command.into_inner() {
+            // keywords that are rules of their own are not arguments, and paths lose their quotes
+            let argument = match argument.as_rule() {
+                Rule::HDR | Rule::BASENAME | Rule::PPUSH => continue,
+                Rule::QUOTED_PATH => argument.into_inner().next().unwrap(),
+                _ => argument,
+            };
+            tokens.push(Token {
+                rule: argument.as_rule(),
+                text: argument.as_str().to_owned(),
+                span: Span::from_pest(fname, &argument.as_span()),
+            });
+        }
+        raw_commands.push(RawCommand { rule, span, tokens });
+    }
+    Ok(raw_commands)
+}
+
+/// loops are unrolled while building, so this many passes is almost certainly a mistake
+const MAX_ITERATIONS: usize = 100_000;
+
+/// how far short of a whole number of steps still counts as reaching the end of a for, since
+/// steps like 0.3 are not exact and would otherwise stop one pass early
+const STEP_TOLERANCE: f32 = 1e-4;
+
+fn is_block_start(rule: Rule) -> bool {
+    matches!(rule, Rule::DEF_S | Rule::REPEAT_DS | Rule::REPEAT_D | Rule::FOR_SDDD | Rule::FOR_SDD | Rule::IF_DCD | Rule::IF_D)
+}
+
+/// finds the else splitting the if block from start to end, if it has one
+fn find_else(commands: &[RawCommand], start: usize, end: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, command) in commands.iter().enumerate().take(end).skip(start) {
+        match command.rule {
+            rule if is_block_start(rule) => depth += 1,
+            Rule::END => depth -= 1,
+            Rule::ELSE if depth == 1 => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// commands that apply to every frame no matter where they are written
+fn is_setting(rule: Rule) -> bool {
+    matches!(
+        rule,
+        Rule::FRAMES_D
+            | Rule::BASENAME_S
+            | Rule::VARY_SDDDD
+            | Rule::VARY_SDDDDD
+            | Rule::VARY_SDDEDD
+            | Rule::SET_SD
+            | Rule::BACKGROUND_DDD
+            | Rule::BACKGROUND_GRADIENT_DDDDDD
+            | Rule::BACKGROUND_IMAGE_P
+            | Rule::BACKGROUND_IMAGE_S
+            | Rule::ANTIALIAS_D
+            | Rule::ANTIALIAS_DS
+            | Rule::HDR
+            | Rule::HDR_S
+            | Rule::EXPOSURE_D
+            | Rule::WEB
+    )
+}
+
+/// finds the end closing the block opened at start
+fn find_end(commands: &[RawCommand], start: usize) -> Result<usize, MdlError> {
+    let mut depth = 0;
+    for (index, command) in commands.iter().enumerate().skip(start) {
+        match command.rule {
+            rule if is_block_start(rule) => depth += 1,
+            Rule::END => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(index);
                 }
-                Rule::BASENAME_S => {
-                    let mut command_contents = command.into_inner();
-                    basename = command_contents.nth(1).unwrap().as_str().to_owned();
+            }
+            _ => {}
+        }
+    }
+    let keyword = commands[start].span.snippet.split_whitespace().next().unwrap_or("block");
+    Err(MdlError::with_span(format!("{} without a matching end", keyword), &commands[start].span))
+}
+
+/// replaces macro parameters and loop variables with what they are bound to, and works out
+/// any expressions
+fn substitute(tokens: &[Token], bindings: &Bindings) -> Result<Vec<Token>, MdlError> {
+    tokens
+        .iter()
+        .map(|token| match token.rule {
+            Rule::PARAM => {
+                let name = token.text.trim_start_matches('{').trim_end_matches('}');
+                bindings
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| MdlError::with_span(format!("no parameter named {} here", name), &token.span))
+            }
+            Rule::EXPR => evaluate(token, bindings),
+            _ => Ok(token.clone()),
+        })
+        .collect()
+}
+
+/// fills the bound values into an expression and works it out like a vary equation. One
+/// that uses knobs or frame_num is left as an expression to be worked out every frame.
+fn evaluate(expression: &Token, bindings: &Bindings) -> Result<Token, MdlError> {
+    let values: HashMap<String, String> = bindings.iter().map(|(name, token)| (name.clone(), token.text.clone())).collect();
+    let expression_error = |error: String| MdlError::with_span(format!("Unable to work out {}: {}", expression.text, error), &expression.span);
+    for name in expression.text.split('{').skip(1).filter_map(|rest| rest.split_once('}')).map(|(name, _)| name) {
+        if !values.contains_key(name) {
+            return Err(expression_error(format!("no parameter named {} here", name)));
+        }
+    }
+    let filled_in = strfmt(&expression.text, &values).map_err(|error| expression_error(error.to_string()))?;
+    let expr = ShuntingParser::parse_str(&filled_in).map_err(expression_error)?;
+    match MathContext::new().eval(&expr) {
+        Ok(value) => Ok(Token {
+            rule: Rule::NUMBER,
+            text: value.to_string(),
+            span: expression.span.clone(),
+        }),
+        Err(error) if error.starts_with("Unknown Variable") => Ok(Token {
+            rule: Rule::EXPR,
+            text: filled_in,
+            span: expression.span.clone(),
+        }),
+        Err(error) => Err(expression_error(error)),
+    }
+}
+
+impl Builder {
+    fn expand(&mut self, commands: &[RawCommand], bindings: &Bindings) -> Result<(), MdlError> {
+        let mut index = 0;
+        while index < commands.len() {
+            let command = &commands[index];
+            match command.rule {
+                Rule::DEF_S => {
+                    let end = find_end(commands, index)?;
+                    self.define(command, &commands[index + 1..end])?;
+                    index = end;
+                }
+                Rule::REPEAT_DS | Rule::REPEAT_D | Rule::FOR_SDDD | Rule::FOR_SDD => {
+                    let end = find_end(commands, index)?;
+                    self.repeat(command, &commands[index + 1..end], bindings)?;
+                    index = end;
                 }
-                Rule::BASENAME => {
-                    println!("WARNING: a default basename will be used instead because basename is missing at {}", error_message);
+                Rule::IF_DCD | Rule::IF_D => {
+                    let end = find_end(commands, index)?;
+                    let (then_body, else_body) = match find_else(commands, index, end) {
+                        Some(middle) => (&commands[index + 1..middle], &commands[middle + 1..end]),
+                        None => (&commands[index + 1..end], &commands[end..end]),
+                    };
+                    self.conditional(command, then_body, else_body, bindings)?;
+                    index = end;
                 }
-                Rule::VARY_SDDDD | Rule::VARY_SDDDDD | Rule::VARY_SDDEDD => {
-                    vary_exists = true;
+                Rule::ELSE => return Err(MdlError::with_span("else without a matching if", &command.span)),
+                Rule::END => return Err(MdlError::with_span("end without a matching def, repeat, for or if", &command.span)),
+                Rule::CALL_S => self.call(command, bindings)?,
+                Rule::INCLUDE_P => self.include(command, bindings)?,
+                _ => {
+                    let tokens = substitute(&command.tokens, bindings)?;
+                    self.build(command.rule, &command.span, &tokens)?;
                 }
-                _ => {}
             }
+            index += 1;
         }
+        Ok(())
     }
-    // pass 1
-    if vary_exists{
-        if !frames_exists{
-            println!("ERROR: vary used without frame numbers included");
-            return;
-        }else{
-            for pair in commands.clone() {
-                for command in pair {
-                    let error_message = command.as_str();
-                    match command.as_rule() {
-                        Rule::VARY_SDDDD | Rule::VARY_SDDDDD => {
-                            let mut command_contents = command.into_inner();
-                            let knob_name = command_contents.next().unwrap().as_str();
-                            let start_frame: u32 = command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid start frame number at {}", error_message));
-                            let end_frame: u32 = command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid end frame number at {}", error_message));
-                            if end_frame < start_frame {
-                                println!("ERROR: start frame number is greater than end frame number at {}", error_message);
-                                return;
-                            }
-                            let start_value: f32 = command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid start knob value at {}", error_message));
-                            let end_value: f32 = command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid end knob value at {}", error_message));
-                            let frame_count = end_frame - start_frame;
-                            let mut power_used: f32 = 1.0;
-                            if let Some(power_input) = command_contents.next(){
-                                power_used = power_input.as_str().parse().expect(&*format!("Not a valid power value at {}", error_message));
-                            }
-                            let mut current_value = start_value;
-                            let change_in_value = (end_value - start_value) / frame_count as f32;
-                            for frame_num in start_frame..=end_frame{
-                                if power_used == 1.0{
-                                    frames[frame_num as usize].insert(knob_name, current_value);
-                                    current_value += change_in_value;
-                                }else if end_value - start_value == 0.0{
-                                    frames[frame_num as usize].insert(knob_name, start_value);
-                                }else{
-                                    let frame_result = ((1.0/frame_count as f32) * (frame_num - start_frame) as f32).powf(power_used);
-                                    frames[frame_num as usize].insert(knob_name, frame_result);
-                                }
-                            }
-                        }
-                        Rule::VARY_SDDEDD => {
-                            let mut command_contents = command.into_inner();
-                            let knob_name = command_contents.next().unwrap().as_str();
-                            let start_frame: u32 = command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid start frame number at {}", error_message));
-                            let end_frame: u32 = command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid end frame number at {}", error_message));
-                            if end_frame < start_frame {
-                                println!("ERROR: start frame number is greater than end frame number at {}", error_message);
-                                return;
-                            }
-                            let equation = command_contents.next().unwrap().as_str();
-                            let min_value: f32 = command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid start knob value at {}", error_message));
-                            let max_value: f32 = command_contents.next().unwrap().as_str().parse().expect(&*format!("Not a valid end knob value at {}", error_message));
-                            let frame_count = end_frame - start_frame;
-                            let mut fmt_map = HashMap::new();
-                            for frame_num in start_frame..=end_frame{
-                                fmt_map.insert("frame_num".to_string(), frame_num);
-                                let expr = ShuntingParser::parse_str(&*strfmt(equation, &fmt_map).unwrap()).expect("Unable to process mafs");
-                                let result = MathContext::new().eval(&expr).unwrap() as f32;
-                                if result > max_value{
-                                    frames[frame_num as usize].insert(knob_name, max_value);
-                                }else if result < min_value{
-                                    frames[frame_num as usize].insert(knob_name, min_value);
-                                }else{
-                                    frames[frame_num as usize].insert(knob_name, result);
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+
+    fn define(&mut self, command: &RawCommand, body: &[RawCommand]) -> Result<(), MdlError> {
+        let name = &command.tokens[0];
+        // macros are made while reading, so one inside a block would be made again every
+        // pass of a loop, or even when the if around it never runs
+        if self.if_depth > 0 || self.loop_depth > 0 || !self.call_stack.is_empty() {
+            return Err(MdlError::with_span("def has to be outside of every repeat, for, if and macro", &command.span));
+        }
+        if self.macros.contains_key(&name.text) {
+            return Err(MdlError::with_span(format!("a macro named {} is already defined", name.text), &name.span));
+        }
+        let params = command.tokens[1..].to_vec();
+        for (index, param) in params.iter().enumerate() {
+            if params[..index].iter().any(|earlier| earlier.text == param.text) {
+                return Err(MdlError::with_span(format!("{} takes two parameters named {}", name.text, param.text), &param.span));
             }
         }
+        self.macros.insert(name.text.clone(), Macro { params, body: body.to_vec() });
+        Ok(())
     }
-    // pass 2
-    for frame_num in 0..frames.len(){
-        for pair in commands.clone() {
-            for command in pair {
-                let error_message = command.as_str();
-                match command.as_rule() {
-                    Rule::CONSTANTS_SDDDDDDDDD => {
-                        let mut command_contents = command.into_inner();
-                        let name = command_contents.next().unwrap().as_str();
-                        let constant = Constant::new(command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), 0.0, 0.0, 0.0);
-                        constants_store.insert(name, constant);
-                    }
-                    Rule::CONSTANTS_SSDDDDDDDDD => {
-                        let mut command_contents = command.into_inner();
-                        let name = command_contents.next().unwrap().as_str();
-                        let vary_value = frames[frame_num].get(command_contents.next().unwrap().as_str()).expect("Unable to get constants");
-                        let constant = Constant::new(command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, command_contents.next().unwrap().as_str().parse::<f32>().expect(error_message)*vary_value, 0.0, 0.0, 0.0);
-                        constants_store.insert(name, constant);
-                    }
-                    // Rule::CONSTANTS_SDDDDDDDDDDDD => {
-                    //     let mut command_contents = command.into_inner();
-                    //     let name = command_contents.next().unwrap().as_str();
-                    //     let constant = Constants::new(command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message), command_contents.next().unwrap().as_str().parse().expect(error_message));
-                    //     constants_store.insert(name, constant);
-                    // }
-                    Rule::PPUSH => {
-                        cstack.push(cstack.last().unwrap().clone());
-                    }
-                    Rule::PPUSH_S => {
-                        let mut command_contents = command.into_inner();
-                        let name = command_contents.nth(1).unwrap().as_str();
-                        let matrix = csystems.get(name).unwrap().clone();
-                        cstack.push(matrix);
-                    }
-                    Rule::PPOP => {
-                        cstack.pop();
-                    }
-                    Rule::MOVE_DDD | Rule::MOVE_DDDS => {
-                        let mut command_contents = command.into_inner();
-                        let mut translate = Matrix::make_translate_with_scale(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            if let Some(knob_name) = command_contents.next(){
-                                if frames[frame_num].contains_key(knob_name.as_str()){
-                                    *frames[frame_num].get(&*knob_name.as_str()).expect(error_message)
-                                }else{
-                                    0.0
-                                }
-                            }else{
-                                1.0
-                            }
-                        );
-                        translate.multiply_matrixes(&cstack.pop().unwrap());
-                        cstack.push(translate);
-                    }
-                    Rule::ROTATE_SD | Rule::ROTATE_SDS => {
-                        let mut command_contents = command.into_inner();
-                        let rot_axis = command_contents.next().unwrap().as_str();
-                        let mut rot_amount: f32 = command_contents.next().unwrap().as_str().parse().expect(error_message);
-                        if let Some(knob_name) = command_contents.next(){
-                            rot_amount *= if frames[frame_num].contains_key(knob_name.as_str()){
-                                *frames[frame_num].get(knob_name.as_str()).expect(error_message)
-                            }else {
-                                0.0
-                            }
-                        }
-                        match rot_axis {
-                            "x" => {
-                                let mut rot = Matrix::make_rot_x(rot_amount);
-                                rot.multiply_matrixes(&cstack.pop().unwrap());
-                                cstack.push(rot);
-                            }
-                            "y" => {
-                                let mut rot = Matrix::make_rot_y(rot_amount);
-                                rot.multiply_matrixes(&cstack.pop().unwrap());
-                                cstack.push(rot);
-                            }
-                            "z" => {
-                                let mut rot = Matrix::make_rot_z(rot_amount);
-                                rot.multiply_matrixes(&cstack.pop().unwrap());
-                                cstack.push(rot);
-                            }
-                            _ => {
-                                panic!(
-                                    "ERROR: Invalid input {} at {} for rotation: please use x, y, or z.",
-                                    rot_axis, error_message
-                                );
-                            }
-                        }
-                    }
-                    Rule::SCALE_DDD | Rule::SCALE_DDDS => {
-                        let mut command_contents = command.into_inner();
-                        let mut scale = Matrix::make_scale_with_scale(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            if let Some(knob_name) = command_contents.next(){
-                                if frames[frame_num].contains_key(knob_name.as_str()){
-                                    *frames[frame_num].get(&*knob_name.as_str()).expect(error_message)
-                                }else{
-                                    0.0
-                                }
-                            }else{
-                                1.0
-                            }
-                        );
-                        scale.multiply_matrixes(&cstack.pop().unwrap());
-                        cstack.push(scale);
-                    }
-                    Rule::SPHERE_SDDDD | Rule::SPHERE_SDDDDS => {
-                        // println!("{:?}", command);
-                        let mut command_contents = command.into_inner();
-                        let lighting_constants = constants_store.get(command_contents.next().unwrap().as_str()).expect("Unable to get lighting constants");
-                        polygons.add_sphere(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            consts::STEP_3D,
-                        );
-
-                        if let Some(coord_system) = command_contents.next(){
-                            polygons.multiply_matrixes(csystems.get(coord_system.as_str()).expect("Unable to find coordinate system"));
-                        }else{
-                            polygons.multiply_matrixes(cstack.last().unwrap());
-                        }
-                        
-                        screen.draw_polygons(
-                            &polygons,
-                            &color,
-                            &mut consts::VIEW.to_vec(),
-                            &consts::AMBIENT_COLOR,
-                            &mut consts::POINT_LIGHT_LOCATION.to_vec(),
-                            &consts::POINT_LIGHT_COLOR,
-                            &lighting_constants.ambient_reflect,
-                            &lighting_constants.diffuse_reflect,
-                            &lighting_constants.specular_reflect
-                        );
-    
-                        polygons = Matrix::new(0, 0);
-                    }
-                    Rule::SPHERE_DDDD | Rule::SPHERE_DDDDS => {
-                        let mut command_contents = command.into_inner();
-                        polygons.add_sphere(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            consts::STEP_3D,
-                        );
-
-                        if let Some(coord_system) = command_contents.next(){
-                            polygons.multiply_matrixes(csystems.get(coord_system.as_str()).expect("Unable to find coordinate system"));
-                        }else{
-                            polygons.multiply_matrixes(cstack.last().unwrap());
-                        }
-
-                        screen.draw_polygons(
-                            &polygons,
-                            &color,
-                            &mut consts::VIEW.to_vec(),
-                            &consts::AMBIENT_COLOR,
-                            &mut consts::POINT_LIGHT_LOCATION.to_vec(),
-                            &consts::POINT_LIGHT_COLOR,
-                            &consts::AMBIENT_REFLECT,
-                            &consts::DIFFUSE_REFLECT,
-                            &consts::SPECULAR_REFLECT
-                        );
-    
-                        polygons = Matrix::new(0, 0);
-                    }
-                    Rule::BOX_SDDDDDD | Rule::BOX_SDDDDDDS => {
-                        let mut command_contents = command.into_inner();
-                        let lighting_constants = constants_store.get(command_contents.next().unwrap().as_str()).expect("Unable to get lighting constants");
-                        polygons.add_box(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message)
-                        );
-                        
-                        if let Some(coord_system) = command_contents.next(){
-                            polygons.multiply_matrixes(csystems.get(coord_system.as_str()).expect("Unable to find coordinate system"));
-                        }else{
-                            polygons.multiply_matrixes(cstack.last().unwrap());
-                        }
-    
-                        screen.draw_polygons(
-                            &polygons,
-                            &color,
-                            &mut consts::VIEW.to_vec(),
-                            &consts::AMBIENT_COLOR,
-                            &mut consts::POINT_LIGHT_LOCATION.to_vec(),
-                            &consts::POINT_LIGHT_COLOR,
-                            &lighting_constants.ambient_reflect,
-                            &lighting_constants.diffuse_reflect,
-                            &lighting_constants.specular_reflect
-                        );
-    
-                        polygons = Matrix::new(0, 0);
-                    }
-                    Rule::BOX_DDDDDD | Rule::BOX_DDDDDDS => {
-                        let mut command_contents = command.into_inner();
-                        polygons.add_box(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message)
-                        );
-
-                        if let Some(coord_system) = command_contents.next(){
-                            polygons.multiply_matrixes(csystems.get(coord_system.as_str()).expect("Unable to find coordinate system"));
-                        }else{
-                            polygons.multiply_matrixes(cstack.last().unwrap());
-                        }
-
-                        screen.draw_polygons(
-                            &polygons,
-                            &color,
-                            &mut consts::VIEW.to_vec(),
-                            &consts::AMBIENT_COLOR,
-                            &mut consts::POINT_LIGHT_LOCATION.to_vec(),
-                            &consts::POINT_LIGHT_COLOR,
-                            &consts::AMBIENT_REFLECT,
-                            &consts::DIFFUSE_REFLECT,
-                            &consts::SPECULAR_REFLECT
-                        );
-    
-                        polygons = Matrix::new(0, 0);
-                    }
-                    Rule::TORUS_SDDDDD | Rule::TORUS_SDDDDDS => {
-                        let mut command_contents = command.into_inner();
-                        let lighting_constants = constants_store.get(command_contents.next().unwrap().as_str()).expect("Unable to get lighting constants");
-                        polygons.add_torus(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            consts::STEP_3D
-                        );
-
-                        if let Some(coord_system) = command_contents.next(){
-                            polygons.multiply_matrixes(csystems.get(coord_system.as_str()).expect("Unable to find coordinate system"));
-                        }else{
-                            polygons.multiply_matrixes(cstack.last().unwrap());
-                        }
-
-                        screen.draw_polygons(
-                            &polygons,
-                            &color,
-                            &mut consts::VIEW.to_vec(),
-                            &consts::AMBIENT_COLOR,
-                            &mut consts::POINT_LIGHT_LOCATION.to_vec(),
-                            &consts::POINT_LIGHT_COLOR,
-                            &lighting_constants.ambient_reflect,
-                            &lighting_constants.diffuse_reflect,
-                            &lighting_constants.specular_reflect
-                        );
-    
-                        polygons = Matrix::new(0, 0);
-                    }
-                    Rule::TORUS_DDDDD | Rule::TORUS_DDDDDS => {
-                        let mut command_contents = command.into_inner();
-                        polygons.add_torus(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            consts::STEP_3D
-                        );
-                        if let Some(coord_system) = command_contents.next(){
-                            polygons.multiply_matrixes(csystems.get(coord_system.as_str()).expect("Unable to find coordinate system"));
-                        }else{
-                            polygons.multiply_matrixes(cstack.last().unwrap());
-                        }
-                        screen.draw_polygons(
-                            &polygons,
-                            &color,
-                            &mut consts::VIEW.to_vec(),
-                            &consts::AMBIENT_COLOR,
-                            &mut consts::POINT_LIGHT_LOCATION.to_vec(),
-                            &consts::POINT_LIGHT_COLOR,
-                            &consts::AMBIENT_REFLECT,
-                            &consts::DIFFUSE_REFLECT,
-                            &consts::SPECULAR_REFLECT
-                        );
-    
-                        polygons = Matrix::new(0, 0);
-                    }
-                    Rule::DISPLAY => {
-                        if frames.len() <= 1{
-                            screen.display();
-                        }
-                    }
-                    Rule::SAVE_S => {
-                        if frames.len() <= 1{
-                            let mut command_contents = command.into_inner();
-                            let filename = command_contents.next().unwrap().as_str();
-                            screen.create_file(filename);
-                            Command::new("magick")
-                                .arg("convert")
-                                .arg(filename)
-                                .arg(filename)
-                                .spawn()
-                                .expect("failed to convert image to desired format");
-                        }
-                    }
-                    Rule::LINE_DDDDDD => {
-                        let mut command_contents = command.into_inner();
-                        edges.add_edge(
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                            command_contents.next().unwrap().as_str().parse().expect(error_message),
-                        );
-                        edges.multiply_matrixes(cstack.last().unwrap());
-                        screen.draw_lines(&edges, &color);
-        
-                        edges = Matrix::new(0, 0);
+
+    /// unrolls a loop, expanding its body once for every value of the loop variable
+    fn repeat(&mut self, command: &RawCommand, body: &[RawCommand], bindings: &Bindings) -> Result<(), MdlError> {
+        let tokens = substitute(&command.tokens, bindings)?;
+        let mut arguments = tokens.iter();
+        let (variable, values) = if matches!(command.rule, Rule::REPEAT_DS | Rule::REPEAT_D) {
+            let count: usize = next_number(&mut arguments, "Not a valid repeat count")?;
+            if count > MAX_ITERATIONS {
+                return Err(MdlError::with_span(format!("repeat can run at most {} times", MAX_ITERATIONS), &command.span));
+            }
+            (arguments.next(), (0..count).map(|value| value as f32).collect::<Vec<f32>>())
+        } else {
+            let variable = arguments.next();
+            let start: f32 = next_number(&mut arguments, "Not a valid start value")?;
+            let end: f32 = next_number(&mut arguments, "Not a valid end value")?;
+            let step = match arguments.next() {
+                Some(step) => parse_number(step, "Not a valid step")?,
+                None if end < start => -1.0,
+                None => 1.0,
+            };
+            if step == 0.0 || (end - start) * step < 0.0 {
+                return Err(MdlError::with_span(format!("stepping by {} never gets from {} to {}", step, start, end), &command.span));
+            }
+            let count = ((end - start) / step + STEP_TOLERANCE).floor() as usize + 1;
+            if count > MAX_ITERATIONS {
+                return Err(MdlError::with_span(format!("for can run at most {} times", MAX_ITERATIONS), &command.span));
+            }
+            (variable, (0..count).map(|index| start + step * index as f32).collect())
+        };
+        let mut loop_bindings = bindings.clone();
+        self.loop_depth += 1;
+        let mut expanded = Ok(());
+        for value in values {
+            if let Some(variable) = variable {
+                let value_token = Token {
+                    rule: Rule::NUMBER,
+                    text: value.to_string(),
+                    span: variable.span.clone(),
+                };
+                loop_bindings.insert(variable.text.clone(), value_token);
+            }
+            expanded = self.expand(body, &loop_bindings);
+            if expanded.is_err() {
+                break;
+            }
+        }
+        self.loop_depth -= 1;
+        expanded
+    }
+
+    /// builds both parts of an if into statements of their own, chosen between every frame
+    fn conditional(&mut self, command: &RawCommand, then_body: &[RawCommand], else_body: &[RawCommand], bindings: &Bindings) -> Result<(), MdlError> {
+        let tokens = substitute(&command.tokens, bindings)?;
+        let mut arguments = tokens.iter();
+        let left = next_value(&mut arguments)?;
+        let comparison = match arguments.next() {
+            Some(comparison) => {
+                let comparison = match comparison.text.as_str() {
+                    "<" => Comparison::Less,
+                    "<=" => Comparison::LessOrEqual,
+                    "==" => Comparison::Equal,
+                    "!=" => Comparison::NotEqual,
+                    ">=" => Comparison::GreaterOrEqual,
+                    _ => Comparison::Greater,
+                };
+                Some((comparison, next_value(&mut arguments)?))
+            }
+            None => None,
+        };
+
+        let outer_statements = std::mem::take(&mut self.script.statements);
+        self.if_depth += 1;
+        let then_expanded = self.expand(then_body, bindings);
+        let then_statements = std::mem::take(&mut self.script.statements);
+        let else_expanded = then_expanded.and_then(|_| self.expand(else_body, bindings));
+        let else_statements = std::mem::replace(&mut self.script.statements, outer_statements);
+        self.if_depth -= 1;
+        else_expanded?;
+
+        let condition = Condition { left, comparison };
+        self.script.statements.push(Statement::new(Command::If { condition, then_statements, else_statements }, command.span.clone()));
+        Ok(())
+    }
+
+    fn call(&mut self, command: &RawCommand, bindings: &Bindings) -> Result<(), MdlError> {
+        let tokens = substitute(&command.tokens, bindings)?;
+        let name = &tokens[0];
+        let arguments = &tokens[1..];
+        let called = self
+            .macros
+            .get(&name.text)
+            .ok_or_else(|| MdlError::with_span(format!("no macro named {} has been defined before this", name.text), &name.span))?;
+        if called.params.len() != arguments.len() {
+            return Err(MdlError::with_span(
+                format!("{} takes {} arguments but was given {}", name.text, called.params.len(), arguments.len()),
+                &command.span,
+            ));
+        }
+        if self.call_stack.contains(&name.text) {
+            return Err(MdlError::with_span(format!("{} calls itself, which would never finish", name.text), &command.span));
+        }
+        let call_bindings: Bindings = called.params.iter().map(|param| param.text.clone()).zip(arguments.iter().cloned()).collect();
+        let body = called.body.clone();
+        self.call_stack.push(name.text.clone());
+        let expanded = self.expand(&body, &call_bindings);
+        self.call_stack.pop();
+        expanded.map_err(|mut error| {
+            error.message = format!("{} (in {} called from {}:{})", error.message, name.text, command.span.file, command.span.line);
+            error
+        })
+    }
+
+    fn include(&mut self, command: &RawCommand, bindings: &Bindings) -> Result<(), MdlError> {
+        let path = &command.tokens[0].text;
+        let included = Path::new(&command.span.file).parent().unwrap_or_else(|| Path::new("")).join(path);
+        let included_name = included.to_string_lossy().to_string();
+        let included_path = canonical_path(&included_name);
+        if let Some(start) = self.include_stack.iter().position(|(file, _)| *file == included_path) {
+            let cycle: Vec<&str> = self.include_stack[start..].iter().map(|(_, name)| name.as_str()).chain([included_name.as_str()]).collect();
+            return Err(MdlError::with_span(format!("include cycle: {}", cycle.join(" -> ")), &command.span));
+        }
+        if !self.script.sources.contains(&included_name) {
+            self.script.sources.push(included_name.clone());
+        }
+        let included_instructions = read_script(&included_name).map_err(|error| MdlError::with_span(error, &command.span))?;
+        let included_commands = read_commands(&included_name, &included_instructions)?;
+        self.include_stack.push((included_path, included_name));
+        let expanded = self.expand(&included_commands, bindings);
+        self.include_stack.pop();
+        expanded
+    }
+
+    /// checks the arguments of an ordinary command and adds it to the script
+    fn build(&mut self, rule: Rule, span: &Span, tokens: &[Token]) -> Result<(), MdlError> {
+        let mut command_contents = tokens.iter();
+        if self.if_depth > 0 && is_setting(rule) {
+            let keyword = span.snippet.split_whitespace().next().unwrap_or("this");
+            return Err(MdlError::with_span(format!("{} sets up the whole render, so it cannot be inside an if", keyword), span));
+        }
+        let statement = match rule {
+            Rule::FRAMES_D => {
+                let frames = next_number(&mut command_contents, "Not a valid frame count")?;
+                self.script.settings.frames = Some(Spanned::new(frames, span.clone()));
+                None
+            }
+            Rule::BASENAME_S => {
+                self.script.settings.basename = Some(command_contents.next().unwrap().text.clone());
+                None
+            }
+            Rule::BASENAME => {
+                warn!("a default basename will be used instead because basename is missing on line {}", span.line);
+                None
+            }
+            Rule::VARY_SDDDD | Rule::VARY_SDDDDD => {
+                let knob = command_contents.next().unwrap().text.clone();
+                let (start_frame, end_frame) = next_frame_range(&mut command_contents, span)?;
+                let start_value = next_number(&mut command_contents, "Not a valid start knob value")?;
+                let end_value = next_number(&mut command_contents, "Not a valid end knob value")?;
+                let power = match command_contents.next() {
+                    Some(power_input) => parse_number(power_input, "Not a valid power value")?,
+                    None => 1.0,
+                };
+                let curve = VaryCurve::Linear { start_value, end_value, power };
+                self.script.varies.push(Spanned::new(Vary { knob, start_frame, end_frame, curve }, span.clone()));
+                None
+            }
+            Rule::VARY_SDDEDD => {
+                let knob = command_contents.next().unwrap().text.clone();
+                let (start_frame, end_frame) = next_frame_range(&mut command_contents, span)?;
+                let equation = next_name(&mut command_contents);
+                let min_value = next_number(&mut command_contents, "Not a valid start knob value")?;
+                let max_value = next_number(&mut command_contents, "Not a valid end knob value")?;
+                let curve = VaryCurve::Equation { equation, min_value, max_value };
+                self.script.varies.push(Spanned::new(Vary { knob, start_frame, end_frame, curve }, span.clone()));
+                None
+            }
+            Rule::SET_SD => {
+                let name = command_contents.next().unwrap().text.clone();
+                let value = next_number(&mut command_contents, "Not a valid knob value")?;
+                self.script.variables.push(Spanned::new(Variable { name, value }, span.clone()));
+                None
+            }
+            Rule::BACKGROUND_DDD => {
+                let color = next_color(&mut command_contents)?;
+                self.script.settings.background = Some(Spanned::new(Background::Solid(color), span.clone()));
+                None
+            }
+            Rule::BACKGROUND_GRADIENT_DDDDDD => {
+                let top = next_color(&mut command_contents)?;
+                let bottom = next_color(&mut command_contents)?;
+                self.script.settings.background = Some(Spanned::new(Background::Gradient(top, bottom), span.clone()));
+                None
+            }
+            Rule::BACKGROUND_IMAGE_P | Rule::BACKGROUND_IMAGE_S => {
+                let filename = command_contents.next().unwrap().text.clone();
+                self.script.settings.background = Some(Spanned::new(Background::Picture(filename), span.clone()));
+                None
+            }
+            Rule::ANTIALIAS_D | Rule::ANTIALIAS_DS => {
+                let factor: usize = next_number(&mut command_contents, "Not a valid antialias factor")?;
+                if factor == 0 || factor > 8 {
+                    return Err(MdlError::with_span("antialias factor must be between 1 and 8", span));
+                }
+                let filter = match command_contents.next().map(|filter| filter.text.as_str()) {
+                    None | Some("box") => Filter::Box,
+                    Some("tent") => Filter::Tent,
+                    Some(filter) => {
+                        return Err(MdlError::with_span(format!("unknown antialias filter {}, please use box or tent", filter), span));
                     }
-                    Rule::SAVE_COORDS_S => {
-                        if let Some(name_canidate) = command.into_inner().next() {
-                            csystems.insert(name_canidate.as_str(), cstack.last().unwrap().clone());
-                        }else{
-                            println!("ERROR: no name passed in for {}", error_message);
-                        }
+                };
+                self.script.settings.antialias = Some((factor, filter));
+                None
+            }
+            Rule::HDR => {
+                self.script.settings.tone_map = Some(ToneMap::Reinhard);
+                None
+            }
+            Rule::HDR_S => {
+                self.script.settings.tone_map = match command_contents.next().unwrap().text.as_str() {
+                    "reinhard" => Some(ToneMap::Reinhard),
+                    "aces" => Some(ToneMap::Aces),
+                    name => {
+                        return Err(MdlError::with_span(format!("unknown tone map {}, please use reinhard or aces", name), span));
                     }
-                    Rule::EOI | Rule::VARY_SDDDD | Rule::VARY_SDDDDD | Rule::VARY_SDDEDD | Rule::BASENAME_S | Rule::BASENAME | Rule::FRAMES_D => {}
-                    _ => {
-                        println!("{:?} was not implemented :/", command.as_rule());
+                };
+                None
+            }
+            Rule::EXPOSURE_D => {
+                self.script.settings.exposure = next_number(&mut command_contents, "Not a valid exposure")?;
+                None
+            }
+            Rule::WEB => {
+                self.script.settings.web = true;
+                None
+            }
+            Rule::CONSTANTS_SDDDDDDDDD | Rule::CONSTANTS_SSDDDDDDDDD => {
+                let name = command_contents.next().unwrap().text.clone();
+                let knob = if rule == Rule::CONSTANTS_SSDDDDDDDDD {
+                    Some(next_name(&mut command_contents))
+                } else {
+                    None
+                };
+                let reflect = next_values(&mut command_contents)?;
+                Some(Command::Constants { name, knob, reflect })
+            }
+            Rule::PPUSH => Some(Command::Push(None)),
+            Rule::PPUSH_S => {
+                Some(Command::Push(Some(next_name(&mut command_contents))))
+            }
+            Rule::PPOP => Some(Command::Pop),
+            Rule::MOVE_DDD | Rule::MOVE_DDDS => {
+                let [x, y, z] = next_values(&mut command_contents)?;
+                Some(Command::Move { x, y, z, knob: optional_name(&mut command_contents) })
+            }
+            Rule::ROTATE_SD | Rule::ROTATE_SDS => {
+                let axis = match command_contents.next().unwrap().text.as_str() {
+                    "x" => Axis::X,
+                    "y" => Axis::Y,
+                    "z" => Axis::Z,
+                    rot_axis => {
+                        return Err(MdlError::with_span(format!("Invalid input {} for rotation: please use x, y, or z.", rot_axis), span));
                     }
+                };
+                let degrees = next_value(&mut command_contents)?;
+                Some(Command::Rotate { axis, degrees, knob: optional_name(&mut command_contents) })
+            }
+            Rule::SCALE_DDD | Rule::SCALE_DDDS => {
+                let [x, y, z] = next_values(&mut command_contents)?;
+                Some(Command::Scale { x, y, z, knob: optional_name(&mut command_contents) })
+            }
+            Rule::SPHERE_SDDDD | Rule::SPHERE_SDDDDS | Rule::SPHERE_DDDD | Rule::SPHERE_DDDDS => {
+                let constants = if matches!(rule, Rule::SPHERE_SDDDD | Rule::SPHERE_SDDDDS) {
+                    Some(next_name(&mut command_contents))
+                } else {
+                    None
+                };
+                let [x, y, z, radius] = next_values(&mut command_contents)?;
+                Some(Command::Sphere { constants, x, y, z, radius, coord_system: optional_name(&mut command_contents) })
+            }
+            Rule::BOX_SDDDDDD | Rule::BOX_SDDDDDDS | Rule::BOX_DDDDDD | Rule::BOX_DDDDDDS => {
+                let constants = if matches!(rule, Rule::BOX_SDDDDDD | Rule::BOX_SDDDDDDS) {
+                    Some(next_name(&mut command_contents))
+                } else {
+                    None
+                };
+                let [x, y, z, width, height, depth] = next_values(&mut command_contents)?;
+                Some(Command::Box { constants, x, y, z, width, height, depth, coord_system: optional_name(&mut command_contents) })
+            }
+            Rule::TORUS_SDDDDD | Rule::TORUS_SDDDDDS | Rule::TORUS_DDDDD | Rule::TORUS_DDDDDS => {
+                let constants = if matches!(rule, Rule::TORUS_SDDDDD | Rule::TORUS_SDDDDDS) {
+                    Some(next_name(&mut command_contents))
+                } else {
+                    None
+                };
+                let [x, y, z, inner_radius, outer_radius] = next_values(&mut command_contents)?;
+                Some(Command::Torus { constants, x, y, z, inner_radius, outer_radius, coord_system: optional_name(&mut command_contents) })
+            }
+            Rule::CIRCLE_DDDD => {
+                let [x, y, z, radius] = next_values(&mut command_contents)?;
+                Some(Command::Circle { x, y, z, radius })
+            }
+            Rule::BEZIER_DDDDDDDD | Rule::HERMITE_DDDDDDDD => {
+                let curve_type = if rule == Rule::BEZIER_DDDDDDDD { CurveType::Bezier } else { CurveType::Hermite };
+                Some(Command::Curve { curve_type, points: next_values(&mut command_contents)? })
+            }
+            Rule::DETAIL_D => {
+                let steps = next_value(&mut command_contents)?;
+                if matches!(steps, Value::Number(steps) if !(consts::MIN_STEP..=consts::MAX_STEP).contains(&(steps.round() as i32))) {
+                    return Err(MdlError::with_span(format!("detail must be between {} and {}", consts::MIN_STEP, consts::MAX_STEP), span));
                 }
+                Some(Command::Detail(Detail::Steps(steps)))
             }
+            Rule::DETAIL_S => match command_contents.next().unwrap().text.as_str() {
+                "auto" => Some(Command::Detail(Detail::Auto)),
+                detail => {
+                    return Err(MdlError::with_span(format!("unknown detail {}, please use a number of steps or auto", detail), span));
+                }
+            },
+            Rule::DISPLAY => Some(Command::Display),
+            Rule::SAVE_S => Some(Command::Save(command_contents.next().unwrap().text.clone())),
+            Rule::SAVE_DEPTH_S => Some(Command::SaveDepth(command_contents.next().unwrap().text.clone())),
+            Rule::LINE_DDDDDD => {
+                let [x0, y0, z0, x1, y1, z1] = next_values(&mut command_contents)?;
+                Some(Command::Line { x0, y0, z0, x1, y1, z1 })
+            }
+            Rule::LINE_STYLE_S => match command_contents.next().unwrap().text.as_str() {
+                "sharp" => Some(Command::LineStyle(LineStyle::Sharp)),
+                "smooth" => Some(Command::LineStyle(LineStyle::Smooth)),
+                style => {
+                    return Err(MdlError::with_span(format!("unknown line style {}, please use sharp or smooth", style), span));
+                }
+            },
+            Rule::LINE_WIDTH_D => {
+                let width = next_value(&mut command_contents)?;
+                if matches!(width, Value::Number(width) if width <= 0.0) {
+                    return Err(MdlError::with_span("line width must be positive", span));
+                }
+                Some(Command::LineWidth(width))
+            }
+            Rule::SHADING_ST => match command_contents.next().unwrap().text.as_str() {
+                "flat" => Some(Command::Shading(Shading::Flat)),
+                "gouraud" => Some(Command::Shading(Shading::Gouraud)),
+                "wireframe" => Some(Command::Shading(Shading::Wireframe)),
+                shading => {
+                    warn!("{} shading was not implemented :/", shading);
+                    None
+                }
+            },
+            Rule::SAVE_COORDS_S => Some(Command::SaveCoordSystem(command_contents.next().unwrap().text.clone())),
+            _ => {
+                warn!("{:?} on line {} was not implemented :/", rule, span.line);
+                None
+            }
+        };
+        if let Some(command) = statement {
+            self.script.statements.push(Statement::new(command, span.clone()));
         }
-        if frames.len() > 1{
-            // println!("{:?}", frames[frame_num]);
-            render_reset_image_canvas(&basename, frame_num, &mut screen, &mut edges, &mut polygons, &mut cstack, &mut constants_store);
-        }
+        Ok(())
     }
-    if frames.len() > 1{
-        make_animation(basename);
+}
+
+/// reads the start and end frame of a vary, which have to be in order
+fn next_frame_range(command_contents: &mut Tokens, span: &Span) -> Result<(usize, usize), MdlError> {
+    let start_frame: usize = next_number(command_contents, "Not a valid start frame number")?;
+    let end_frame: usize = next_number(command_contents, "Not a valid end frame number")?;
+    if end_frame < start_frame {
+        return Err(MdlError::with_span("start frame number is greater than end frame number", span));
     }
+    Ok((start_frame, end_frame))
 }
 
-fn render_reset_image_canvas(filename: &str, frame_num: usize, screen: &mut Image, edges: &mut Matrix, polygons: &mut Matrix, cstack: &mut Vec<Matrix>, constants_store: &mut HashMap<&str, Constant>){
-    let filename = "animation/".to_owned() + &filename + &*format!("{:04}", frame_num) + ".ppm";
-    screen.create_file(&*filename);
-    println!("Rendering {}...", filename);
-    screen.clear();
-    *edges = Matrix::new(0, 0);
-    *polygons = Matrix::new(0, 0);
-    *cstack = vec![Matrix::new(0, 0); 0];
-    cstack.push(Matrix::identity());
-    constants_store.clear();
+/// reads the next three doubles of a command as an rgb color, clamping each to 0-255
+fn next_color(command_contents: &mut Tokens) -> Result<Color, MdlError> {
+    let mut channel = || -> Result<u8, MdlError> {
+        let value: f32 = next_number(command_contents, "Not a valid color value")?;
+        Ok(value.round().clamp(0.0, 255.0) as u8)
+    };
+    let r = channel()?;
+    let g = channel()?;
+    let b = channel()?;
+    Ok(Color::new_color(r, g, b))
+}
+
+/// parses the next argument of a command as a number, pointing at it if that fails
+fn next_number<T: FromStr>(command_contents: &mut Tokens, message: &str) -> Result<T, MdlError> {
+    parse_number(command_contents.next().unwrap(), message)
+}
+
+/// reads a numeric argument that may change from frame to frame
+fn next_value(command_contents: &mut Tokens) -> Result<Value, MdlError> {
+    let argument = command_contents.next().unwrap();
+    // a bare name is only allowed by the grammar where it stands for a knob, as in if
+    if argument.rule != Rule::EXPR && argument.rule != Rule::NAME {
+        return Ok(Value::Number(parse_number(argument, "Not a valid number")?));
+    }
+    let rpn = ShuntingParser::parse_str(&argument.text).map_err(|error| MdlError::with_span(error, &argument.span))?;
+    Ok(Value::Expression(Box::new(Expression {
+        text: argument.text.clone(),
+        rpn,
+        span: argument.span.clone(),
+    })))
+}
+
+fn next_values<const N: usize>(command_contents: &mut Tokens) -> Result<[Value; N], MdlError> {
+    let mut values = Vec::with_capacity(N);
+    for _ in 0..N {
+        values.push(next_value(command_contents)?);
+    }
+    Ok(values.try_into().unwrap())
 }
 
-fn clean_animation_directory(){
-    Command::new("make")
-        .arg("clean_anim")
-        .spawn()
-        .expect("ERROR: unable to delete files");
-}
\ No newline at end of file
+fn parse_number<T: FromStr>(argument: &Token, message: &str) -> Result<T, MdlError> {
+    if argument.rule == Rule::EXPR {
+        return Err(MdlError::with_span(
+            format!("{}: {} uses knobs, which can only be used in the arguments of drawing and transformation commands", message, argument.text),
+            &argument.span,
+        ));
+    }
+    argument.text.parse().map_err(|_| MdlError::with_span(format!("{}: {}", message, argument.text), &argument.span))
+}
+
+fn next_name(command_contents: &mut Tokens) -> Name {
+    let name = command_contents.next().unwrap();
+    Name::new(name.text.clone(), name.span.clone())
+}
+
+/// the trailing knob or coordinate system name some commands take
+fn optional_name(command_contents: &mut Tokens) -> Option<Name> {
+    command_contents.next().map(|name| Name::new(name.text.clone(), name.span.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the x of every move in the script, in order
+    fn moves(source: &str) -> Vec<f32> {
+        let script = parse_source("test.mdl", source).unwrap();
+        script
+            .statements
+            .iter()
+            .map(|statement| match &statement.node {
+                Command::Move { x: Value::Number(x), .. } => *x,
+                _ => panic!("expected only moves"),
+            })
+            .collect()
+    }
+
+    fn background_picture(source: &str) -> String {
+        match parse_source("test.mdl", source).unwrap().settings.background.unwrap().node {
+            Background::Picture(filename) => filename,
+            _ => panic!("expected a background picture"),
+        }
+    }
+
+    #[test]
+    fn background_image_takes_quoted_paths() {
+        assert_eq!(background_picture("background image \"images/sky-1.ppm\"\n"), "images/sky-1.ppm");
+        assert_eq!(background_picture("background image \"../my backgrounds/sky.ppm\"\n"), "../my backgrounds/sky.ppm");
+        assert_eq!(background_picture("background image sky.ppm\n"), "sky.ppm");
+    }
+
+    fn parse_error(source: &str) -> String {
+        match parse_source("test.mdl", source) {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.message,
+        }
+    }
+
+    #[test]
+    fn def_inside_a_loop_is_an_error() {
+        let message = parse_error("repeat 2\ndef nudge\nmove 1 0 0\nend\nend\n");
+        assert!(message.starts_with("def has to be outside"), "{}", message);
+        let message = parse_error("for i from 0 to 1\ndef nudge\nmove {i} 0 0\nend\nend\n");
+        assert!(message.starts_with("def has to be outside"), "{}", message);
+    }
+
+    #[test]
+    fn def_inside_an_if_or_macro_is_an_error() {
+        let message = parse_error("if (frame_num) > 3\ndef nudge\nmove 1 0 0\nend\nend\ncall nudge\n");
+        assert!(message.starts_with("def has to be outside"), "{}", message);
+        let message = parse_error("def outer\ndef inner\nmove 1 0 0\nend\nend\ncall outer\n");
+        assert!(message.starts_with("def has to be outside"), "{}", message);
+    }
+
+    #[test]
+    fn def_at_the_top_level_can_be_called_in_blocks() {
+        assert_eq!(moves("def nudge x\nmove {x} 0 0\nend\nrepeat 2 i\ncall nudge {i}\nend\n"), [0.0, 1.0]);
+    }
+
+    #[test]
+    fn for_reaches_its_end() {
+        assert_eq!(moves("for j from 0 to 3\nmove {j} 0 0\nend\n"), [0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(moves("for j from 3 to 1\nmove {j} 0 0\nend\n"), [3.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn for_reaches_its_end_with_fractional_steps() {
+        let values = moves("for j from 0 to 0.9 step 0.3\nmove {j} 0 0\nend\n");
+        assert_eq!(values.len(), 4);
+        assert!((values[3] - 0.9).abs() < 1e-5);
+        assert_eq!(moves("for j from 1 to 0 step -0.1\nmove {j} 0 0\nend\n").len(), 11);
+    }
+
+    #[test]
+    fn for_stops_before_a_partial_step() {
+        assert_eq!(moves("for j from 0 to 1 step 0.3\nmove {j} 0 0\nend\n").len(), 4);
+    }
+}
@@ -0,0 +1,143 @@
+//! A small png encoder so frames can be written without any image tools installed.
+//! The pixel data is stored in uncompressed deflate blocks, which every png reader accepts.
+
+use crate::color::Color;
+
+/// encodes rows of pixels, top row first, as an 8 bit rgb png
+pub fn encode(pixels: &[Vec<Color>]) -> Vec<u8> {
+    let height = pixels.len();
+    let width = if height > 0 { pixels[0].len() } else { 0 };
+
+    // every row starts with filter type 0, meaning the bytes are stored as is
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in pixels {
+        raw.push(0);
+        for pixel in row {
+            raw.extend_from_slice(&[pixel.r, pixel.g, pixel.b]);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, color type 2 (rgb), default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut result = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    write_chunk(&mut result, b"IHDR", &header);
+    write_chunk(&mut result, b"IDAT", &zlib_store(&raw));
+    write_chunk(&mut result, b"IEND", &[]);
+    result
+}
+
+fn write_chunk(result: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    result.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = result.len();
+    result.extend_from_slice(kind);
+    result.extend_from_slice(data);
+    let crc = crc32(&result[start..]);
+    result.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// wraps data in a zlib stream made of stored deflate blocks
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let mut result = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        result.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        result.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let length = block.len() as u16;
+        result.extend_from_slice(&length.to_le_bytes());
+        result.extend_from_slice(&(!length).to_le_bytes());
+        result.extend_from_slice(block);
+    }
+    result.extend_from_slice(&adler32(data).to_be_bytes());
+    result
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a separate reader for zlib streams of stored blocks, checking the header, the length
+    /// of every block and the adler32 along the way
+    fn inflate(stream: &[u8]) -> Vec<u8> {
+        assert_eq!((stream[0] as u32 * 256 + stream[1] as u32) % 31, 0);
+        let body = &stream[2..stream.len() - 4];
+        let mut output = Vec::new();
+        let mut position = 0;
+        loop {
+            let header = body[position];
+            assert_eq!(header >> 1, 0, "only stored blocks are written");
+            let length = u16::from_le_bytes([body[position + 1], body[position + 2]]);
+            assert_eq!(!length, u16::from_le_bytes([body[position + 3], body[position + 4]]));
+            output.extend_from_slice(&body[position + 5..position + 5 + length as usize]);
+            position += 5 + length as usize;
+            if header & 1 == 1 {
+                break;
+            }
+        }
+        assert_eq!(position, body.len());
+        assert_eq!(u32::from_be_bytes(stream[stream.len() - 4..].try_into().unwrap()), adler32(&output));
+        output
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn stored_data_reads_back() {
+        let counting: Vec<u8> = (0..200_000_u32).map(|index| index as u8).collect();
+        for data in [&b""[..], b"a", &counting[..u16::MAX as usize], &counting[..u16::MAX as usize + 1], &counting] {
+            assert_eq!(inflate(&zlib_store(data)), data);
+        }
+    }
+
+    #[test]
+    fn encoded_png_has_valid_chunks() {
+        let pixels = vec![vec![Color::new_color(255, 0, 0), Color::new_color(0, 0, 255)]; 3];
+        let png = encode(&pixels);
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']);
+        let mut position = 8;
+        let mut data = Vec::new();
+        while position < png.len() {
+            let length = u32::from_be_bytes(png[position..position + 4].try_into().unwrap()) as usize;
+            let chunk = &png[position + 4..position + 8 + length];
+            assert_eq!(u32::from_be_bytes(png[position + 8 + length..position + 12 + length].try_into().unwrap()), crc32(chunk));
+            if &chunk[..4] == b"IDAT" {
+                data.extend_from_slice(&chunk[4..]);
+            }
+            position += 12 + length;
+        }
+        // each row is a filter byte and then the pixels
+        assert_eq!(inflate(&data), [0, 255, 0, 0, 0, 0, 255].repeat(3));
+    }
+}
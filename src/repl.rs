@@ -0,0 +1,136 @@
+//! An interactive prompt that runs MDL commands as they are typed. One canvas, coordinate
+//! stack and set of constants stay alive between lines, so transforms can be tried out a
+//! step at a time and looked at with display or save whenever wanted.
+
+use crate::ast::Script;
+use crate::cli::Options;
+use crate::interpreter::{Frame, Interpreter, Knobs};
+use crate::parser::{self, Macros};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "type MDL commands to run them straight away, blocks like def and for continue
+until their end. display and save show or write the picture so far.
+
+    :undo     take back the last command
+    :stack    print the current coordinate system
+    :reset    start again with an empty canvas
+    :help     print this message
+    :quit     leave, as does end of input";
+
+/// how many commands :undo can take back
+const MAX_UNDO: usize = 100;
+
+/// the name given to typed commands in error messages
+const SOURCE_NAME: &str = "<repl>";
+
+/// everything a command can change, saved before each one so it can be undone
+#[derive(Clone)]
+struct State {
+    interpreter: Interpreter,
+    /// values given with set so far
+    knobs: Knobs,
+    /// macros defined with def so far
+    macros: Macros,
+}
+
+impl State {
+    fn new(options: &Options) -> Result<State, String> {
+        let interpreter = Interpreter::new(&Script::default(), options, false).map_err(|error| error.to_string())?;
+        Ok(State { interpreter, knobs: Knobs::new(), macros: Macros::default() })
+    }
+}
+
+/// reads commands from standard input until it ends or :quit is typed
+pub fn run(options: &Options) -> Result<(), String> {
+    let mut state = State::new(options)?;
+    let mut history: Vec<State> = Vec::new();
+    // lines of a block that has not reached its end yet
+    let mut pending = String::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    info!("{}\n", HELP);
+    loop {
+        print!("{}", if pending.is_empty() { "mdl> " } else { "...> " });
+        io::stdout().flush().map_err(|error| format!("unable to write the prompt because {}", error))?;
+        let line = match lines.next() {
+            Some(line) => line.map_err(|error| format!("unable to read a command because {}", error))?,
+            None => break,
+        };
+        if pending.is_empty() {
+            match line.trim() {
+                "" => continue,
+                ":quit" => break,
+                ":help" => {
+                    println!("{}", HELP);
+                    continue;
+                }
+                ":undo" => {
+                    match history.pop() {
+                        Some(previous) => state = previous,
+                        None => eprintln!("ERROR: there is nothing to undo"),
+                    }
+                    continue;
+                }
+                ":stack" => {
+                    let stack = state.interpreter.stack();
+                    println!("depth {}\n{}", stack.len(), stack.last().unwrap());
+                    continue;
+                }
+                ":reset" => {
+                    remember(&mut history, state);
+                    state = State::new(options)?;
+                    continue;
+                }
+                meta if meta.starts_with(':') => {
+                    eprintln!("ERROR: unknown command {}, :help lists them", meta);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        pending.push_str(&line);
+        pending.push('\n');
+        let (script, macros) = match parser::parse_source_with_macros(SOURCE_NAME, &pending, &state.macros) {
+            Ok(parsed) => parsed,
+            // keep reading until the block is closed
+            Err(error) if error.message.ends_with("without a matching end") => continue,
+            Err(error) => {
+                eprintln!("ERROR: {}", error);
+                pending.clear();
+                continue;
+            }
+        };
+        pending.clear();
+        remember(&mut history, state.clone());
+        state.macros = macros;
+        if let Err(error) = execute(&script, &mut state) {
+            eprintln!("ERROR: {}", error);
+            // commands that failed partway are taken back completely
+            state = history.pop().unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// adds a state to undo back to, forgetting the oldest once there are MAX_UNDO
+fn remember(history: &mut Vec<State>, state: State) {
+    if history.len() == MAX_UNDO {
+        history.remove(0);
+    }
+    history.push(state);
+}
+
+fn execute(script: &Script, state: &mut State) -> Result<(), String> {
+    let settings = &script.settings;
+    if settings.frames.is_some() || settings.basename.is_some() || settings.background.is_some() || settings.antialias.is_some() || settings.tone_map.is_some() || settings.exposure != 0.0 || settings.web || !script.varies.is_empty() {
+        warn!("frames, basename, background, antialias, hdr, exposure, web and vary only apply when rendering a script");
+    }
+    for variable in &script.variables {
+        state.knobs.insert(variable.node.name.clone(), variable.node.value);
+    }
+    let frame = Frame::new(0, &state.knobs);
+    for statement in &script.statements {
+        state.interpreter.execute(statement, &frame).map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Previews pictures right in the terminal, for machines without a window to open them in.
+
+use crate::color::Color;
+use std::env;
+use std::fs::File;
+use std::process::Command;
+
+/// used when the terminal will not say how big it is
+const DEFAULT_SIZE: (usize, usize) = (80, 24);
+
+/// columns and rows of the terminal, from COLUMNS and LINES or else stty
+pub fn size() -> (usize, usize) {
+    let from_env = |name| env::var(name).ok().and_then(|value| value.trim().parse::<usize>().ok()).filter(|&value| value > 0);
+    if let (Some(columns), Some(rows)) = (from_env("COLUMNS"), from_env("LINES")) {
+        return (columns, rows);
+    }
+    // stty reads the size from its standard input, which has to be the terminal itself
+    let stty = File::open("/dev/tty").and_then(|tty| Command::new("stty").arg("size").stdin(tty).output());
+    if let Ok(output) = stty {
+        let size = String::from_utf8_lossy(&output.stdout).to_string();
+        if let Some((rows, columns)) = size.trim().split_once(' ') {
+            if let (Ok(rows), Ok(columns)) = (rows.parse(), columns.parse()) {
+                return (columns, rows);
+            }
+        }
+    }
+    DEFAULT_SIZE
+}
+
+/// draws the pixels with upper half block characters, each showing two pixels with its
+/// foreground and background color. The picture is shrunk to fit in the given columns and
+/// all but the last of the rows, leaving room for the prompt
+pub fn half_blocks(pixels: &[Vec<Color>], columns: usize, rows: usize) -> String {
+    let pixels = shrink(pixels, columns, rows.saturating_sub(1).max(1) * 2);
+    let mut result = String::new();
+    for pair in pixels.chunks(2) {
+        for (x, top) in pair[0].iter().enumerate() {
+            result.push_str(&format!("\x1b[38;2;{};{};{}m", top.r, top.g, top.b));
+            match pair.get(1) {
+                Some(bottom) => {
+                    let bottom = bottom[x];
+                    result.push_str(&format!("\x1b[48;2;{};{};{}m", bottom.r, bottom.g, bottom.b));
+                }
+                // an odd last row leaves the bottom halves to the terminal's own background
+                None => result.push_str("\x1b[49m"),
+            }
+            result.push('\u{2580}');
+        }
+        result.push_str("\x1b[0m\n");
+    }
+    result
+}
+
+/// averages blocks of pixels so the picture fits in width by height, keeping its shape.
+/// Pictures that already fit are left alone
+fn shrink(pixels: &[Vec<Color>], width: usize, height: usize) -> Vec<Vec<Color>> {
+    let pixels_height = pixels.len();
+    let pixels_width = pixels.first().map_or(0, |row| row.len());
+    let scale = (pixels_width as f32 / width as f32).max(pixels_height as f32 / height as f32);
+    if scale <= 1.0 {
+        return pixels.to_vec();
+    }
+    let new_width = ((pixels_width as f32 / scale) as usize).max(1);
+    let new_height = ((pixels_height as f32 / scale) as usize).max(1);
+    (0..new_height)
+        .map(|y| {
+            let top = y * pixels_height / new_height;
+            let bottom = ((y + 1) * pixels_height / new_height).max(top + 1);
+            (0..new_width)
+                .map(|x| {
+                    let left = x * pixels_width / new_width;
+                    let right = ((x + 1) * pixels_width / new_width).max(left + 1);
+                    let mut total = [0u32; 3];
+                    for row in &pixels[top..bottom] {
+                        for color in &row[left..right] {
+                            total[0] += color.r as u32;
+                            total[1] += color.g as u32;
+                            total[2] += color.b as u32;
+                        }
+                    }
+                    let count = ((bottom - top) * (right - left)) as u32;
+                    Color::new_color((total[0] / count) as u8, (total[1] / count) as u8, (total[2] / count) as u8)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// encodes the pixels as sixel graphics at full size, with colors rounded to a 6x6x6 cube
+/// since sixel pictures use a palette
+pub fn sixel(pixels: &[Vec<Color>]) -> String {
+    let height = pixels.len();
+    let width = pixels.first().map_or(0, |row| row.len());
+    let level = |channel: u8| (channel as usize * 5 + 127) / 255;
+    let index = |color: &Color| level(color.r) * 36 + level(color.g) * 6 + level(color.b);
+    let mut result = format!("\x1bPq\"1;1;{};{}", width, height);
+    for red in 0..6 {
+        for green in 0..6 {
+            for blue in 0..6 {
+                result.push_str(&format!("#{};2;{};{};{}", red * 36 + green * 6 + blue, red * 20, green * 20, blue * 20));
+            }
+        }
+    }
+    // each band is six rows, drawn once for every color used in it
+    for band in pixels.chunks(6) {
+        let mut used = [false; 216];
+        for row in band {
+            for color in row {
+                used[index(color)] = true;
+            }
+        }
+        for (color_index, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+            result.push_str(&format!("#{}", color_index));
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = band.iter().enumerate().filter(|(_, row)| index(&row[x]) == color_index).fold(0, |bits, (y, _)| bits | 1 << y);
+                let character = char::from(63 + bits as u8);
+                run = match run {
+                    Some((previous, count)) if previous == character => Some((previous, count + 1)),
+                    Some((previous, count)) => {
+                        push_run(&mut result, previous, count);
+                        Some((character, 1))
+                    }
+                    None => Some((character, 1)),
+                };
+            }
+            if let Some((previous, count)) = run {
+                push_run(&mut result, previous, count);
+            }
+            // back to the start of the band for the next color
+            result.push('$');
+        }
+        result.push('-');
+    }
+    result.push_str("\x1b\\");
+    result
+}
+
+fn push_run(result: &mut String, character: char, count: usize) {
+    if count > 3 {
+        result.push_str(&format!("!{}{}", count, character));
+    } else {
+        result.extend(std::iter::repeat_n(character, count));
+    }
+}
@@ -0,0 +1,44 @@
+//! Renders the still again every time the script, or a file it reads, is saved. Files are
+//! polled for their modification time so nothing beyond the standard library is needed.
+
+use crate::cli::Options;
+use std::fs;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// renders until the program is interrupted. Failed renders are printed by render and then
+/// waited out like any other, since the next save will probably fix them
+pub fn watch(options: &Options, render: fn(&Options, &mut Vec<String>) -> bool) -> ! {
+    let mut options = options.clone();
+    options.still_only = true;
+    loop {
+        let mut sources = vec![options.script.clone()];
+        render(&options, &mut sources);
+        let mut seen = Vec::new();
+        sources.retain(|source| {
+            let first = !seen.contains(source);
+            seen.push(source.clone());
+            first
+        });
+        info!("Watching {} for changes, press Ctrl-C to stop", sources.join(", "));
+        let times = modified_times(&sources);
+        let changed = loop {
+            thread::sleep(POLL_INTERVAL);
+            let new_times = modified_times(&sources);
+            if let Some(index) = (0..sources.len()).find(|&index| new_times[index] != times[index]) {
+                break &sources[index];
+            }
+        };
+        info!("\n{} changed, rendering again", changed);
+    }
+}
+
+/// None for files that cannot be read, so one being deleted or created also counts as a change
+fn modified_times(files: &[String]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| fs::metadata(file).and_then(|metadata| metadata.modified()).ok())
+        .collect()
+}
@@ -0,0 +1,171 @@
+//! Writes the page made by the web command: a single html file that plays back the rendered
+//! frames next to their knob values. The frames are embedded as png data uris, so the page
+//! can be shared on its own without a server or the output directory.
+
+use crate::color::Color;
+use crate::interpreter::Knobs;
+use crate::png;
+use std::fs;
+
+/// how long each frame is shown while playing
+const FRAME_DELAY_MS: u32 = 40;
+
+const TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>$TITLE</title>
+<style>
+    body { background: #222; color: #ddd; font-family: sans-serif; margin: 2em; }
+    main { display: flex; gap: 2em; align-items: flex-start; flex-wrap: wrap; }
+    img { display: block; max-width: 100%; }
+    .controls { display: flex; gap: 1em; align-items: center; margin-top: 1em; }
+    .controls input { flex: 1; }
+    table { border-collapse: collapse; font-family: monospace; }
+    td { padding: 0.2em 0.8em; border-bottom: 1px solid #444; }
+    td:last-child { text-align: right; }
+</style>
+</head>
+<body>
+<h1>$TITLE</h1>
+<main>
+    <div>
+        <img id="frame" alt="rendered frame">
+        <div class="controls" id="controls">
+            <button id="play">pause</button>
+            <input id="scrubber" type="range" min="0" value="0">
+            <span id="number"></span>
+        </div>
+    </div>
+    <table id="knobs"></table>
+</main>
+<script>
+const frames = [$FRAMES];
+const image = document.getElementById("frame");
+const scrubber = document.getElementById("scrubber");
+const play = document.getElementById("play");
+let current = 0;
+let timer = null;
+
+function show(index) {
+    current = index;
+    const frame = frames[index];
+    image.src = frame.image;
+    scrubber.value = index;
+    document.getElementById("number").textContent = "frame " + frame.number;
+    const knobs = document.getElementById("knobs");
+    knobs.replaceChildren();
+    for (const [name, value] of Object.entries(frame.knobs)) {
+        const row = knobs.insertRow();
+        row.insertCell().textContent = name;
+        row.insertCell().textContent = value === null ? "-" : value.toFixed(4);
+    }
+}
+
+function toggle() {
+    if (timer === null) {
+        timer = setInterval(() => show((current + 1) % frames.length), $DELAY);
+        play.textContent = "pause";
+    } else {
+        clearInterval(timer);
+        timer = null;
+        play.textContent = "play";
+    }
+}
+
+scrubber.max = frames.length - 1;
+scrubber.addEventListener("input", () => show(Number(scrubber.value)));
+play.addEventListener("click", toggle);
+document.addEventListener("keydown", (event) => {
+    if (event.key === " ") {
+        event.preventDefault();
+        toggle();
+    } else if (event.key === "ArrowRight") {
+        show((current + 1) % frames.length);
+    } else if (event.key === "ArrowLeft") {
+        show((current + frames.length - 1) % frames.length);
+    }
+});
+show(0);
+if (frames.length > 1) {
+    toggle();
+} else {
+    document.getElementById("controls").hidden = true;
+}
+</script>
+</body>
+</html>
+"#;
+
+/// one rendered frame, as it will appear in the page
+pub struct WebFrame {
+    pub number: usize,
+    pub png: Vec<u8>,
+    pub knobs: Knobs,
+}
+
+impl WebFrame {
+    pub fn new(number: usize, pixels: &[Vec<Color>], knobs: &Knobs) -> WebFrame {
+        WebFrame {
+            number,
+            png: png::encode(pixels),
+            knobs: knobs.clone(),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let mut names: Vec<&String> = self.knobs.keys().collect();
+        names.sort();
+        let knobs: Vec<String> = names
+            .into_iter()
+            .map(|name| {
+                let value = self.knobs[name];
+                // json has no infinity or nan
+                let value = if value.is_finite() { value.to_string() } else { String::from("null") };
+                format!("\"{}\": {}", escape_json(name), value)
+            })
+            .collect();
+        format!(
+            "{{\"number\": {}, \"image\": \"data:image/png;base64,{}\", \"knobs\": {{{}}}}}",
+            self.number,
+            base64(&self.png),
+            knobs.join(", ")
+        )
+    }
+}
+
+pub fn write_viewer(filename: &str, title: &str, frames: &[WebFrame]) -> Result<(), String> {
+    info!("Writing {}...", filename);
+    let frames: Vec<String> = frames.iter().map(WebFrame::to_json).collect();
+    let page = TEMPLATE
+        .replace("$TITLE", &escape_html(title))
+        .replace("$DELAY", &FRAME_DELAY_MS.to_string())
+        .replace("$FRAMES", &frames.join(",\n"));
+    fs::write(filename, page).map_err(|error| format!("failed to write {} because {}", filename, error))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for index in 0..4 {
+            // a chunk of n bytes fills n + 1 characters, and = pads the rest
+            if index <= chunk.len() {
+                result.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn escape_json(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}